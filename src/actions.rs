@@ -1,9 +1,9 @@
-use gpui::actions;
+use gpui::{actions, impl_actions};
+use serde::Deserialize;
 
 actions!(
     app,
     [
-        NewTerminal,
         ToggleRoster,
         ToggleLens,
         ToggleLogs,
@@ -13,4 +13,14 @@ actions!(
         SwitchToLogs,
         SwitchToReports,
     ]
-);
\ No newline at end of file
+);
+
+/// Opens a terminal with the named profile, or the default profile if
+/// none is given.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct NewTerminal {
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+impl_actions!(app, [NewTerminal]);
\ No newline at end of file
@@ -1,8 +1,28 @@
 // use gpui::*; // Commented out for CLI version
+use crate::ai_cli::{AICLIType, UsageParser, UsageParserFactory, UsageUpdate};
+use crate::logs::{self, field, LogLevel, LogPanel};
+use crate::report::{Report, ReportMetrics, ReportStatus, ReportWindow};
+use crate::settings::AgentSettings;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Upper bound for the delay between two automatic restarts.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Output kept per run report; anything beyond is dropped.
+const MAX_REPORT_OUTPUT: usize = 1024 * 1024;
+
+/// How long a finished run's report waits for its output readers to drain
+/// the pipes, which a surviving grandchild may keep open.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_millis(250);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Agent {
@@ -13,6 +33,71 @@ pub struct Agent {
     pub status: AgentStatus,
     pub created_at: u64,
     pub pid: Option<u32>,
+    #[serde(default)]
+    pub auto_start: bool,
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+    #[serde(default = "default_restart_backoff_ms")]
+    pub restart_backoff_ms: u64,
+    /// Automatic restarts since the agent was last started by hand.
+    #[serde(default)]
+    pub restart_count: u32,
+    /// Exit code of the last run; `None` if it was killed by a signal.
+    #[serde(default)]
+    pub last_exit_code: Option<i32>,
+    /// Signal sent first when the agent is asked to stop.
+    #[serde(default)]
+    pub stop_signal: StopSignal,
+    /// Working directory; inherits Synapse's own when unset.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Start from an empty environment so only `env` is visible to the agent.
+    #[serde(default)]
+    pub env_clear: bool,
+    /// Fed to the agent's stdin. When unset the agent reads from
+    /// `/dev/null` on Unix, where it runs in its own process group.
+    #[serde(default)]
+    pub stdin: Option<StdinSource>,
+    #[serde(default)]
+    pub depends_on: Vec<AgentDependency>,
+    /// Id of the `ai_cli` tool this agent runs and the model it uses, so
+    /// its runs can be priced.
+    #[serde(default)]
+    pub tool: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AgentDependency {
+    pub agent_id: String,
+    pub condition: DependencyCondition,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum DependencyCondition {
+    /// Start once the dependency has been launched.
+    #[default]
+    Started,
+    /// Start once the dependency has exited with status 0.
+    Succeeded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DependencyState {
+    Satisfied,
+    Pending,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum StdinSource {
+    File(PathBuf),
+    Text(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -21,23 +106,478 @@ pub enum AgentStatus {
     Starting,
     Running,
     Stopping,
+    Restarting,
+    /// Waiting for a free slot under `AgentSettings::max_concurrent`.
+    Queued,
+    /// Terminated by the watchdog after exceeding `AgentSettings::timeout_seconds`.
+    TimedOut,
+    /// Held back by `start_all` until its dependencies are satisfied.
+    Waiting,
     Error(String),
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    pub fn should_restart(&self, succeeded: bool) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !succeeded,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+/// The polite signal used before escalating to SIGKILL. Interactive tools such
+/// as Aider save their state on SIGINT, most daemons expect SIGTERM.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum StopSignal {
+    Interrupt,
+    #[default]
+    Terminate,
+}
+
+fn default_max_restarts() -> u32 {
+    5
+}
+
+fn default_restart_backoff_ms() -> u64 {
+    1000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A single line of output produced by a running agent.
+#[derive(Debug, Clone)]
+pub struct AgentOutput {
+    pub agent_id: String,
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+type OutputSubscribers = Arc<Mutex<HashMap<String, Vec<Sender<AgentOutput>>>>>;
+
+/// Output and counters collected for the report of a single run.
+#[derive(Default)]
+struct RunCapture {
+    output: String,
+    truncated: bool,
+    errors: u64,
+    warnings: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    api_calls: u64,
+    /// Session cost as last reported by the tool.
+    tool_cost: Option<f64>,
+    /// Whether the tool has reported any usage at all.
+    has_usage: bool,
+}
+
+impl RunCapture {
+    fn record(&mut self, level: &LogLevel, line: &str) {
+        match level {
+            LogLevel::Error | LogLevel::Critical => self.errors += 1,
+            LogLevel::Warn => self.warnings += 1,
+            _ => {}
+        }
+
+        if self.output.len() + line.len() + 1 > MAX_REPORT_OUTPUT {
+            if !self.truncated {
+                self.output.push_str("[output truncated]\n");
+                self.truncated = true;
+            }
+            return;
+        }
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn record_usage(&mut self, usage: UsageUpdate) {
+        self.has_usage = true;
+        self.input_tokens += usage.input_tokens;
+        self.output_tokens += usage.output_tokens;
+        self.api_calls += usage.api_calls;
+        if usage.session_cost.is_some() {
+            self.tool_cost = usage.session_cost;
+        }
+    }
+
+    fn metrics(&self) -> ReportMetrics {
+        let tokens = |count: u64| self.has_usage.then_some(count);
+        ReportMetrics {
+            tokens_used: tokens(self.input_tokens + self.output_tokens),
+            api_calls: self.api_calls,
+            errors: self.errors,
+            warnings: self.warnings,
+            input_tokens: tokens(self.input_tokens),
+            output_tokens: tokens(self.output_tokens),
+            cost: self.tool_cost,
+        }
+    }
+}
+
+/// Everything a reader thread needs to forward an agent's output.
+#[derive(Clone)]
+struct OutputSinks {
+    agent_id: String,
+    agent_name: String,
+    log_panel: Arc<Mutex<LogPanel>>,
+    subscribers: OutputSubscribers,
+    capture: Arc<Mutex<RunCapture>>,
+    /// Shared by the stdout and stderr readers of a run.
+    usage_parser: Option<Arc<Mutex<Box<dyn UsageParser>>>>,
+    report_window: Arc<Mutex<ReportWindow>>,
+    report_id: String,
+}
+
+impl OutputSinks {
+    fn publish(&self, stream: OutputStream, line: String) {
+        // Agents logging JSON get their level and fields picked up
+        let json = logs::parse_json_line(&line);
+        let level = match (json.as_ref().and_then(|j| j.level.clone()), stream) {
+            (Some(level), _) => level,
+            (None, OutputStream::Stdout) => LogLevel::Info,
+            (None, OutputStream::Stderr) => stderr_level(&line),
+        };
+
+        let usage = self
+            .usage_parser
+            .as_ref()
+            .and_then(|parser| parser.lock().ok()?.parse_line(&line));
+
+        if let Ok(mut capture) = self.capture.lock() {
+            capture.record(&level, &line);
+            if let Some(usage) = usage {
+                capture.record_usage(usage);
+                // Keep the live report current while the run is going
+                if let Ok(mut report_window) = self.report_window.lock() {
+                    report_window.set_metrics(&self.report_id, capture.metrics());
+                }
+            }
+        }
+
+        if let Ok(mut panel) = self.log_panel.lock() {
+            let (message, mut metadata) = match json {
+                Some(json) => (json.message, json.fields),
+                None => (line.clone(), Default::default()),
+            };
+            metadata.insert(field::AGENT_ID.to_string(), self.agent_id.clone());
+            metadata.insert(field::REPORT_ID.to_string(), self.report_id.clone());
+            panel.log_with_fields(level, &self.agent_name, message, metadata);
+        }
+
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            if let Some(senders) = subscribers.get_mut(&self.agent_id) {
+                // Drop subscribers whose receiving end has gone away
+                senders.retain(|tx| {
+                    tx.send(AgentOutput {
+                        agent_id: self.agent_id.clone(),
+                        stream,
+                        line: line.clone(),
+                    })
+                    .is_ok()
+                });
+            }
+        }
+    }
+}
+
+fn stderr_level(line: &str) -> LogLevel {
+    let lower = line.to_lowercase();
+    if lower.contains("error") || lower.contains("fatal") || lower.contains("panic") {
+        LogLevel::Error
+    } else {
+        LogLevel::Warn
+    }
+}
+
+/// Forwards output line by line and signals `done` at end of stream.
+fn spawn_output_reader<R: Read + Send + 'static>(
+    reader: R,
+    stream: OutputStream,
+    sinks: OutputSinks,
+    done: Sender<()>,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) => break, // EOF
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buffer);
+                    sinks.publish(stream, line.trim_end_matches(['\r', '\n']).to_string());
+                }
+                Err(_) => break,
+            }
+        }
+        let _ = done.send(());
+    });
+}
+
+struct AgentProcess {
+    agent_id: String,
+    child: Child,
+    started: Instant,
+    report_id: String,
+    capture: Arc<Mutex<RunCapture>>,
+    /// Receives one message from each of the `reader_count` output readers
+    /// once it has drained its pipe.
+    readers_done: Receiver<()>,
+    reader_count: usize,
+}
+
+fn new_report(agent: &Agent) -> Report {
+    let mut title = agent.command.clone();
+    for arg in &agent.args {
+        title.push(' ');
+        title.push_str(arg);
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let mut report = Report::new(format!("report_{}", nanos), title, agent.name.clone());
+    report.tool = agent.tool.clone();
+    report.model = agent.model.clone();
+    report
+}
+
+/// Files a report for a launch that never got as far as a running process.
+fn report_failed_start(report_window: &Arc<Mutex<ReportWindow>>, agent: &Agent, error: &str) {
+    let mut report = new_report(agent);
+    let report_id = report.id.clone();
+    report.metrics.errors = 1;
+
+    let mut report_window = report_window.lock().unwrap();
+    report_window.add_report(report);
+    report_window.update_report(&report_id, ReportStatus::Failed, error.to_string());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TerminationReason {
+    Stop,
+    Timeout,
+}
+
+/// An agent that has been signalled and must exit before `deadline`, after
+/// which its process group is killed.
+struct Termination {
+    pid: u32,
+    reason: TerminationReason,
+    deadline: Instant,
+}
+
+// Agents are spawned as process group leaders, so signalling the negated pid
+// reaches every process they started as well.
+#[cfg(unix)]
+fn signal_process_group(child: &Child, signal: libc::c_int) -> std::io::Result<()> {
+    let ret = unsafe { libc::kill(-(child.id() as libc::pid_t), signal) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(unix)]
+fn send_stop_signal(child: &mut Child, signal: StopSignal) -> std::io::Result<()> {
+    let signal = match signal {
+        StopSignal::Interrupt => libc::SIGINT,
+        StopSignal::Terminate => libc::SIGTERM,
+    };
+    signal_process_group(child, signal)
+}
+
+#[cfg(not(unix))]
+fn send_stop_signal(child: &mut Child, _signal: StopSignal) -> std::io::Result<()> {
+    child.kill()
+}
+
+#[cfg(unix)]
+fn kill_process_group(child: &mut Child) -> std::io::Result<()> {
+    signal_process_group(child, libc::SIGKILL)
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut Child) -> std::io::Result<()> {
+    child.kill()
+}
+
 pub struct AgentManager {
     agents: Vec<Agent>,
-    processes: Arc<Mutex<Vec<(String, Child)>>>,
+    processes: Arc<Mutex<Vec<AgentProcess>>>,
+    agents_path: PathBuf,
+    log_panel: Arc<Mutex<LogPanel>>,
+    report_window: Arc<Mutex<ReportWindow>>,
+    output_subscribers: OutputSubscribers,
+    /// Usage parsers registered per tool id, taking precedence over the
+    /// built-in parser of the matching `AICLIType`.
+    usage_parsers: HashMap<String, UsageParserFactory>,
+    pending_restarts: Vec<(String, Instant)>,
+    launch_queue: VecDeque<String>,
+    terminations: Vec<Termination>,
+    /// Agents started by `start_all` or `start_auto_agents` whose
+    /// dependencies are not satisfied yet.
+    waiting: Vec<String>,
+    /// Threads completing the reports of finished runs.
+    report_finishers: Vec<JoinHandle<()>>,
+    /// Outcome of each agent's most recent finished run (`true` on success).
+    last_runs: HashMap<String, bool>,
+    max_concurrent: usize,
+    timeout: Option<Duration>,
+    stop_grace: Duration,
 }
 
 impl AgentManager {
     pub fn new() -> Self {
+        Self::with_path(Self::get_agents_path())
+    }
+
+    /// Keeps the agent definitions in `agents_path` instead of
+    /// `~/.synapse/agents.json`.
+    pub fn with_path(agents_path: PathBuf) -> Self {
+        let agents = Self::load_agents(&agents_path).unwrap_or_else(|e| {
+            set_aside_unreadable(&agents_path, &e);
+            Vec::new()
+        });
+
         Self {
-            agents: Vec::new(),
+            agents,
             processes: Arc::new(Mutex::new(Vec::new())),
+            agents_path,
+            log_panel: Arc::new(Mutex::new(LogPanel::new(&mut ()))),
+            report_window: Arc::new(Mutex::new(ReportWindow::new(&mut ()))),
+            output_subscribers: Arc::new(Mutex::new(HashMap::new())),
+            usage_parsers: HashMap::new(),
+            pending_restarts: Vec::new(),
+            launch_queue: VecDeque::new(),
+            terminations: Vec::new(),
+            waiting: Vec::new(),
+            report_finishers: Vec::new(),
+            last_runs: HashMap::new(),
+            max_concurrent: 0,
+            timeout: None,
+            stop_grace: Duration::from_secs(crate::settings::default_stop_grace_seconds()),
+        }
+    }
+
+    /// Applies the concurrency limit, run timeout and stop grace period. A
+    /// zero concurrency limit or timeout disables that limit.
+    pub fn apply_settings(&mut self, settings: &AgentSettings) {
+        self.max_concurrent = settings.max_concurrent;
+        self.stop_grace = Duration::from_secs(settings.stop_grace_seconds);
+        self.timeout = if settings.timeout_seconds > 0 {
+            Some(Duration::from_secs(settings.timeout_seconds))
+        } else {
+            None
+        };
+    }
+
+    fn at_capacity(&self) -> bool {
+        self.max_concurrent > 0 && self.processes.lock().unwrap().len() >= self.max_concurrent
+    }
+
+    /// Routes agent output into a log panel shared with the rest of the app.
+    pub fn set_log_panel(&mut self, log_panel: Arc<Mutex<LogPanel>>) {
+        self.log_panel = log_panel;
+    }
+
+    pub fn log_panel(&self) -> Arc<Mutex<LogPanel>> {
+        self.log_panel.clone()
+    }
+
+    /// Files a report for every agent run into a window shared with the app.
+    pub fn set_report_window(&mut self, report_window: Arc<Mutex<ReportWindow>>) {
+        self.report_window = report_window;
+    }
+
+    pub fn report_window(&self) -> Arc<Mutex<ReportWindow>> {
+        self.report_window.clone()
+    }
+
+    /// Uses `factory` to extract token usage from the output of agents whose
+    /// `tool` is `tool_id`.
+    pub fn register_usage_parser(&mut self, tool_id: &str, factory: UsageParserFactory) {
+        self.usage_parsers.insert(tool_id.to_string(), factory);
+    }
+
+    fn usage_parser_for(&self, agent: &Agent) -> Option<Box<dyn UsageParser>> {
+        let tool = agent.tool.as_deref()?;
+        match self.usage_parsers.get(tool) {
+            Some(factory) => Some(factory()),
+            None => AICLIType::from_tool_id(tool).usage_parser(),
+        }
+    }
+
+    /// Streams every line the agent writes to stdout or stderr from now on.
+    /// The subscription ends when the receiver is dropped.
+    pub fn subscribe_output(&self, id: &str) -> Receiver<AgentOutput> {
+        let (tx, rx) = mpsc::channel();
+        self.output_subscribers
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default()
+            .push(tx);
+        rx
+    }
+
+    fn get_agents_path() -> PathBuf {
+        if let Some(home) = dirs::home_dir() {
+            home.join(".synapse").join("agents.json")
+        } else {
+            PathBuf::from("./synapse_agents.json")
+        }
+    }
+
+    fn load_agents(path: &PathBuf) -> anyhow::Result<Vec<Agent>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(path)?;
+        let mut agents = serde_json::from_str::<Vec<Agent>>(&contents)?;
+        // Runtime state from a previous session is meaningless now
+        for agent in &mut agents {
+            agent.status = AgentStatus::Stopped;
+            agent.pid = None;
+        }
+        Ok(agents)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.agents_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(&self.agents)?;
+        fs::write(&self.agents_path, json)?;
+        Ok(())
+    }
+
+    /// Launches every agent flagged with `auto_start`, provided auto-start is
+    /// enabled globally, honouring dependencies the way `start_all` does.
+    /// Fails without starting anything if the dependency graph has a cycle;
+    /// otherwise returns the ids of agents that failed to start.
+    pub fn start_auto_agents(&mut self, settings: &AgentSettings) -> anyhow::Result<Vec<String>> {
+        if !settings.auto_start {
+            return Ok(Vec::new());
         }
+        self.start_in_order(|agent| agent.auto_start)
     }
 
-    pub fn add_agent(&mut self, name: String, command: String, args: Vec<String>) -> Agent {
+    pub fn add_agent(&mut self, name: String, command: String, args: Vec<String>) -> anyhow::Result<Agent> {
         let id = format!("agent_{}", SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -54,32 +594,470 @@ impl AgentManager {
                 .unwrap()
                 .as_secs(),
             pid: None,
+            auto_start: false,
+            restart_policy: RestartPolicy::Never,
+            max_restarts: default_max_restarts(),
+            restart_backoff_ms: default_restart_backoff_ms(),
+            restart_count: 0,
+            last_exit_code: None,
+            stop_signal: StopSignal::Terminate,
+            cwd: None,
+            env: HashMap::new(),
+            env_clear: false,
+            stdin: None,
+            depends_on: Vec::new(),
+            tool: None,
+            model: None,
         };
 
         self.agents.push(agent.clone());
-        agent
+        if let Err(e) = self.save() {
+            self.agents.pop();
+            return Err(e);
+        }
+        Ok(agent)
+    }
+
+    pub fn set_auto_start(&mut self, id: &str, auto_start: bool) -> anyhow::Result<()> {
+        let agent = self
+            .agents
+            .iter_mut()
+            .find(|a| a.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Agent not found"))?;
+        agent.auto_start = auto_start;
+        self.save()
+    }
+
+    /// Applies `f` to the agent's definition and persists the result. Changes
+    /// to the command or environment take effect on the next start.
+    pub fn update_agent<F>(&mut self, id: &str, f: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&mut Agent),
+    {
+        let agent = self
+            .agents
+            .iter_mut()
+            .find(|a| a.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Agent not found"))?;
+        f(agent);
+        self.save()
+    }
+
+    /// Declares that `id` must not start before `dependency_id` satisfies
+    /// `condition`. Rejected if it would introduce a cycle.
+    pub fn add_dependency(
+        &mut self,
+        id: &str,
+        dependency_id: &str,
+        condition: DependencyCondition,
+    ) -> anyhow::Result<()> {
+        if self.get_agent(dependency_id).is_none() {
+            return Err(anyhow::anyhow!("Dependency agent not found"));
+        }
+        let agent = self
+            .agents
+            .iter_mut()
+            .find(|a| a.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Agent not found"))?;
+        let previous = agent.depends_on.clone();
+        agent.depends_on.retain(|d| d.agent_id != dependency_id);
+        agent.depends_on.push(AgentDependency {
+            agent_id: dependency_id.to_string(),
+            condition,
+        });
+
+        if let Err(e) = self.startup_order() {
+            if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
+                agent.depends_on = previous;
+            }
+            return Err(e);
+        }
+        self.save()
+    }
+
+    pub fn remove_dependency(&mut self, id: &str, dependency_id: &str) -> anyhow::Result<()> {
+        self.update_agent(id, |agent| {
+            agent.depends_on.retain(|d| d.agent_id != dependency_id);
+        })
+    }
+
+    /// Orders all agents so that every agent comes after its dependencies.
+    fn startup_order(&self) -> anyhow::Result<Vec<String>> {
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for agent in &self.agents {
+            let mut count = 0;
+            for dep in &agent.depends_on {
+                if self.get_agent(&dep.agent_id).is_none() {
+                    return Err(anyhow::anyhow!(
+                        "Agent '{}' depends on unknown agent {}",
+                        agent.name,
+                        dep.agent_id
+                    ));
+                }
+                dependents.entry(dep.agent_id.as_str()).or_default().push(agent.id.as_str());
+                count += 1;
+            }
+            remaining.insert(agent.id.as_str(), count);
+        }
+
+        // Kahn's algorithm, seeded in registration order for a stable result
+        let mut ready: VecDeque<&str> = self
+            .agents
+            .iter()
+            .filter(|a| remaining[a.id.as_str()] == 0)
+            .map(|a| a.id.as_str())
+            .collect();
+        let mut order = Vec::with_capacity(self.agents.len());
+        while let Some(id) = ready.pop_front() {
+            order.push(id.to_string());
+            for dependent in dependents.get(id).into_iter().flatten() {
+                let count = remaining.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+
+        if order.len() < self.agents.len() {
+            let ordered: HashSet<&str> = order.iter().map(|id| id.as_str()).collect();
+            let mut stuck: HashSet<&str> = remaining
+                .keys()
+                .copied()
+                .filter(|id| !ordered.contains(id))
+                .collect();
+            // Agents that merely depend on a cycle are left over as well; peel
+            // off those nothing else in the leftover set depends on
+            loop {
+                let downstream: Vec<&str> = stuck
+                    .iter()
+                    .copied()
+                    .filter(|id| {
+                        !dependents
+                            .get(id)
+                            .into_iter()
+                            .flatten()
+                            .any(|d| stuck.contains(d))
+                    })
+                    .collect();
+                if downstream.is_empty() {
+                    break;
+                }
+                for id in downstream {
+                    stuck.remove(id);
+                }
+            }
+            let cyclic: Vec<&str> = self
+                .agents
+                .iter()
+                .filter(|a| stuck.contains(a.id.as_str()))
+                .map(|a| a.name.as_str())
+                .collect();
+            return Err(anyhow::anyhow!(
+                "Dependency cycle between agents: {}",
+                cyclic.join(", ")
+            ));
+        }
+        Ok(order)
+    }
+
+    /// Starts every agent that isn't already active, dependencies first.
+    /// Agents whose dependencies aren't satisfied yet are put in `Waiting`
+    /// and launched by `update_agent_status` once they are. Fails without
+    /// starting anything if the dependency graph has a cycle; otherwise
+    /// returns the ids of agents that failed to start.
+    pub fn start_all(&mut self) -> anyhow::Result<Vec<String>> {
+        self.start_in_order(|_| true)
+    }
+
+    /// Starts the agents selected by `include` in dependency order, parking
+    /// those with unsatisfied dependencies in `Waiting`.
+    fn start_in_order<F>(&mut self, include: F) -> anyhow::Result<Vec<String>>
+    where
+        F: Fn(&Agent) -> bool,
+    {
+        let order = self.startup_order()?;
+        let mut failed = Vec::new();
+
+        for id in order {
+            let Some(agent) = self.get_agent(&id) else {
+                continue;
+            };
+            if !include(agent) {
+                continue;
+            }
+            if matches!(
+                agent.status,
+                AgentStatus::Starting
+                    | AgentStatus::Running
+                    | AgentStatus::Restarting
+                    | AgentStatus::Queued
+                    | AgentStatus::Waiting
+            ) {
+                continue;
+            }
+
+            match self.dependencies_state(&id) {
+                DependencyState::Satisfied => {
+                    if self.start_agent(&id).is_err() {
+                        failed.push(id);
+                    }
+                }
+                DependencyState::Pending => {
+                    self.last_runs.remove(&id);
+                    if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
+                        agent.status = AgentStatus::Waiting;
+                    }
+                    self.waiting.push(id);
+                }
+                DependencyState::Failed => {
+                    self.fail_on_dependency(&id);
+                    failed.push(id);
+                }
+            }
+        }
+
+        Ok(failed)
+    }
+
+    fn dependency_state(&self, dependency: &AgentDependency) -> DependencyState {
+        let Some(agent) = self.get_agent(&dependency.agent_id) else {
+            return DependencyState::Failed;
+        };
+        let last_run = self.last_runs.get(&dependency.agent_id).copied();
+
+        match (dependency.condition, &agent.status) {
+            (DependencyCondition::Started, AgentStatus::Running) => DependencyState::Satisfied,
+            (DependencyCondition::Started, _) if last_run.is_some() => DependencyState::Satisfied,
+            (DependencyCondition::Succeeded, _) if last_run == Some(true) => DependencyState::Satisfied,
+            (
+                _,
+                AgentStatus::Starting
+                | AgentStatus::Running
+                | AgentStatus::Restarting
+                | AgentStatus::Queued
+                | AgentStatus::Waiting,
+            ) => DependencyState::Pending,
+            _ => DependencyState::Failed,
+        }
+    }
+
+    fn dependencies_state(&self, id: &str) -> DependencyState {
+        let Some(agent) = self.get_agent(id) else {
+            return DependencyState::Failed;
+        };
+        let mut state = DependencyState::Satisfied;
+        for dependency in &agent.depends_on {
+            match self.dependency_state(dependency) {
+                DependencyState::Failed => return DependencyState::Failed,
+                DependencyState::Pending => state = DependencyState::Pending,
+                DependencyState::Satisfied => {}
+            }
+        }
+        state
+    }
+
+    fn fail_on_dependency(&mut self, id: &str) {
+        self.waiting.retain(|wid| wid != id);
+        if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
+            agent.status = AgentStatus::Error("A dependency did not start or failed".to_string());
+        }
+    }
+
+    /// Launches waiting agents whose dependencies became satisfied and fails
+    /// those whose dependencies can no longer be satisfied.
+    fn resolve_waiting(&mut self) {
+        // Loop until stable so chains of `Started` dependencies resolve in one call
+        loop {
+            let mut changed = false;
+            for id in self.waiting.clone() {
+                match self.dependencies_state(&id) {
+                    DependencyState::Satisfied => {
+                        self.waiting.retain(|wid| *wid != id);
+                        // A failed launch leaves the agent in the Error state
+                        let _ = self.start_agent(&id);
+                        changed = true;
+                    }
+                    DependencyState::Failed => {
+                        self.fail_on_dependency(&id);
+                        changed = true;
+                    }
+                    DependencyState::Pending => {}
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    pub fn set_restart_policy(
+        &mut self,
+        id: &str,
+        policy: RestartPolicy,
+        max_restarts: u32,
+        backoff_ms: u64,
+    ) -> anyhow::Result<()> {
+        let agent = self
+            .agents
+            .iter_mut()
+            .find(|a| a.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Agent not found"))?;
+        agent.restart_policy = policy;
+        agent.max_restarts = max_restarts;
+        agent.restart_backoff_ms = backoff_ms;
+        self.save()
     }
 
     pub fn start_agent(&mut self, id: &str) -> anyhow::Result<()> {
+        self.ensure_not_running(id)?;
+        self.pending_restarts.retain(|(aid, _)| aid != id);
+        self.waiting.retain(|wid| wid != id);
+        self.last_runs.remove(id);
+        if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
+            agent.restart_count = 0;
+        }
+        self.launch_agent(id)
+    }
+
+    /// Fails while a process of the agent has not been reaped yet, including
+    /// one that was asked to stop but hasn't exited.
+    fn ensure_not_running(&self, id: &str) -> anyhow::Result<()> {
+        if self.processes.lock().unwrap().iter().any(|p| p.agent_id == id) {
+            return Err(anyhow::anyhow!("Agent is still running"));
+        }
+        Ok(())
+    }
+
+    /// Whether any agent is running, stopping or due to be launched.
+    pub fn has_active_agents(&self) -> bool {
+        !self.processes.lock().unwrap().is_empty()
+            || !self.pending_restarts.is_empty()
+            || !self.launch_queue.is_empty()
+            || !self.waiting.is_empty()
+    }
+
+    fn launch_agent(&mut self, id: &str) -> anyhow::Result<()> {
+        self.ensure_not_running(id)?;
+        if self.at_capacity() {
+            let agent = self
+                .agents
+                .iter_mut()
+                .find(|a| a.id == id)
+                .ok_or_else(|| anyhow::anyhow!("Agent not found"))?;
+            agent.status = AgentStatus::Queued;
+            if !self.launch_queue.iter().any(|qid| qid == id) {
+                self.launch_queue.push_back(id.to_string());
+            }
+            return Ok(());
+        }
+
+        let usage_parser = self
+            .agents
+            .iter()
+            .find(|a| a.id == id)
+            .and_then(|agent| self.usage_parser_for(agent));
+
         if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
             agent.status = AgentStatus::Starting;
 
             let mut cmd = Command::new(&agent.command);
             cmd.args(&agent.args);
+            if agent.env_clear {
+                cmd.env_clear();
+            }
+            cmd.envs(&agent.env);
+            if let Some(ref cwd) = agent.cwd {
+                cmd.current_dir(cwd);
+            }
+            match agent.stdin {
+                Some(StdinSource::File(ref path)) => match File::open(path) {
+                    Ok(file) => {
+                        cmd.stdin(Stdio::from(file));
+                    }
+                    Err(e) => {
+                        let message = format!("Failed to open stdin file {}: {}", path.display(), e);
+                        report_failed_start(&self.report_window, agent, &message);
+                        agent.status = AgentStatus::Error(e.to_string());
+                        return Err(anyhow::anyhow!(message));
+                    }
+                },
+                Some(StdinSource::Text(_)) => {
+                    cmd.stdin(Stdio::piped());
+                }
+                // A background process group that reads the terminal is
+                // stopped with SIGTTIN, so detached agents get no stdin
+                #[cfg(unix)]
+                None => {
+                    cmd.stdin(Stdio::null());
+                }
+                #[cfg(not(unix))]
+                None => {}
+            }
             cmd.stdout(Stdio::piped());
             cmd.stderr(Stdio::piped());
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+                cmd.process_group(0);
+            }
 
             match cmd.spawn() {
-                Ok(child) => {
+                Ok(mut child) => {
+                    let report = new_report(agent);
+                    let report_id = report.id.clone();
+                    self.report_window.lock().unwrap().add_report(report);
+
+                    let capture = Arc::new(Mutex::new(RunCapture::default()));
+                    let sinks = OutputSinks {
+                        agent_id: agent.id.clone(),
+                        agent_name: agent.name.clone(),
+                        log_panel: self.log_panel.clone(),
+                        subscribers: self.output_subscribers.clone(),
+                        capture: capture.clone(),
+                        usage_parser: usage_parser.map(|parser| Arc::new(Mutex::new(parser))),
+                        report_window: self.report_window.clone(),
+                        report_id: report_id.clone(),
+                    };
+                    let (done_tx, readers_done) = mpsc::channel();
+                    let mut reader_count = 0;
+                    if let Some(stdout) = child.stdout.take() {
+                        spawn_output_reader(stdout, OutputStream::Stdout, sinks.clone(), done_tx.clone());
+                        reader_count += 1;
+                    }
+                    if let Some(stderr) = child.stderr.take() {
+                        spawn_output_reader(stderr, OutputStream::Stderr, sinks, done_tx);
+                        reader_count += 1;
+                    }
+                    if let (Some(mut stdin), Some(StdinSource::Text(text))) =
+                        (child.stdin.take(), agent.stdin.clone())
+                    {
+                        // Written from a thread so a large input can't block
+                        // us on a full pipe; dropping stdin signals EOF
+                        thread::spawn(move || {
+                            let _ = stdin.write_all(text.as_bytes());
+                        });
+                    }
+
                     let pid = child.id();
                     agent.pid = Some(pid);
                     agent.status = AgentStatus::Running;
                     
                     let mut processes = self.processes.lock().unwrap();
-                    processes.push((id.to_string(), child));
+                    processes.push(AgentProcess {
+                        agent_id: id.to_string(),
+                        child,
+                        started: Instant::now(),
+                        report_id,
+                        capture,
+                        readers_done,
+                        reader_count,
+                    });
                     Ok(())
                 }
                 Err(e) => {
+                    report_failed_start(&self.report_window, agent, &format!("Failed to start agent: {}", e));
                     agent.status = AgentStatus::Error(e.to_string());
                     Err(anyhow::anyhow!("Failed to start agent: {}", e))
                 }
@@ -89,36 +1067,83 @@ impl AgentManager {
         }
     }
 
+    /// Asks the agent to exit with its `stop_signal`. The agent's process
+    /// group is killed if it is still alive once the grace period is over;
+    /// `update_agent_status` performs the escalation and the final transition
+    /// to `Stopped`.
     pub fn stop_agent(&mut self, id: &str) -> anyhow::Result<()> {
+        self.pending_restarts.retain(|(aid, _)| aid != id);
+        self.launch_queue.retain(|qid| qid != id);
+        self.waiting.retain(|wid| wid != id);
         if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
-            agent.status = AgentStatus::Stopping;
-
             let mut processes = self.processes.lock().unwrap();
-            if let Some(pos) = processes.iter().position(|(aid, _)| aid == id) {
-                let (_, mut child) = processes.remove(pos);
-                if let Err(e) = child.kill() {
-                    agent.status = AgentStatus::Error(e.to_string());
-                    return Err(anyhow::anyhow!("Failed to kill agent: {}", e));
-                }
+            let Some(process) = processes.iter_mut().find(|p| p.agent_id == id) else {
+                agent.status = AgentStatus::Stopped;
+                agent.pid = None;
+                return Ok(());
+            };
+
+            agent.status = AgentStatus::Stopping;
+            if let Err(e) = send_stop_signal(&mut process.child, agent.stop_signal) {
+                agent.status = AgentStatus::Error(e.to_string());
+                return Err(anyhow::anyhow!("Failed to signal agent: {}", e));
             }
 
-            agent.status = AgentStatus::Stopped;
-            agent.pid = None;
+            // A manual stop supersedes a pending timeout
+            let pid = process.child.id();
+            self.terminations.retain(|t| t.pid != pid);
+            self.terminations.push(Termination {
+                pid,
+                reason: TerminationReason::Stop,
+                deadline: Instant::now() + self.stop_grace,
+            });
             Ok(())
         } else {
             Err(anyhow::anyhow!("Agent not found"))
         }
     }
 
+    /// Stops every running agent and blocks until they have exited, killing
+    /// those that outlast the grace period. Meant to be called on app exit.
+    pub fn shutdown(&mut self) {
+        let ids: Vec<String> = self.agents.iter().map(|a| a.id.clone()).collect();
+        for id in ids {
+            let _ = self.stop_agent(&id);
+        }
+
+        let deadline = Instant::now() + self.stop_grace + Duration::from_secs(1);
+        while !self.processes.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+            self.update_agent_status();
+        }
+
+        // Each finisher gives up after OUTPUT_DRAIN_TIMEOUT
+        for finisher in self.report_finishers.drain(..) {
+            let _ = finisher.join();
+        }
+    }
+
     pub fn remove_agent(&mut self, id: &str) -> anyhow::Result<()> {
         if let Some(agent) = self.agents.iter().find(|a| a.id == id) {
-            if agent.status == AgentStatus::Running {
+            if matches!(
+                agent.status,
+                AgentStatus::Running
+                    | AgentStatus::Restarting
+                    | AgentStatus::Queued
+                    | AgentStatus::Stopping
+                    | AgentStatus::Waiting
+            ) {
                 self.stop_agent(id)?;
             }
         }
 
         self.agents.retain(|a| a.id != id);
-        Ok(())
+        self.last_runs.remove(id);
+        for agent in &mut self.agents {
+            agent.depends_on.retain(|d| d.agent_id != id);
+        }
+        self.output_subscribers.lock().unwrap().remove(id);
+        self.save()
     }
 
     pub fn get_agents(&self) -> &[Agent] {
@@ -130,41 +1155,292 @@ impl AgentManager {
     }
 
     pub fn update_agent_status(&mut self) {
-        let mut processes = self.processes.lock().unwrap();
-        let mut finished_ids = Vec::new();
-        
-        for (id, child) in processes.iter_mut() {
-            match child.try_wait() {
-                Ok(Some(_)) => {
-                    // Process finished
-                    finished_ids.push(id.clone());
-                }
-                Ok(None) => {
-                    // Still running
-                }
-                Err(_) => {
-                    // Error checking status
-                    finished_ids.push(id.clone());
+        let mut finished = Vec::new();
+        {
+            let mut processes = self.processes.lock().unwrap();
+            for process in processes.iter_mut() {
+                match process.child.try_wait() {
+                    Ok(Some(status)) => {
+                        // Process finished; take down anything it left behind
+                        // if we were the ones asking it to exit
+                        if self.terminations.iter().any(|t| t.pid == process.child.id()) {
+                            let _ = kill_process_group(&mut process.child);
+                        }
+                        finished.push((process.agent_id.clone(), process.child.id(), status.code()));
+                    }
+                    Ok(None) => {
+                        // Still running
+                    }
+                    Err(_) => {
+                        // Error checking status
+                        finished.push((process.agent_id.clone(), process.child.id(), None));
+                    }
                 }
             }
         }
-        
-        // Remove finished processes
-        processes.retain(|(id, _)| !finished_ids.contains(id));
-        
-        // Update agent statuses
-        for id in finished_ids {
+
+        // Update agent statuses and schedule restarts
+        let now = Instant::now();
+        for (id, pid, exit_code) in finished {
+            let process = {
+                let mut processes = self.processes.lock().unwrap();
+                let Some(pos) = processes.iter().position(|p| p.child.id() == pid) else {
+                    continue;
+                };
+                processes.remove(pos)
+            };
+
+            let reason = self
+                .terminations
+                .iter()
+                .find(|t| t.pid == pid)
+                .map(|t| t.reason);
+            self.terminations.retain(|t| t.pid != pid);
+
+            let succeeded = exit_code == Some(0);
+            self.last_runs.insert(id.clone(), succeeded);
+            self.finish_report(process, exit_code, reason);
+
             if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
-                agent.status = AgentStatus::Stopped;
                 agent.pid = None;
+                agent.last_exit_code = exit_code;
+
+                // Stopped and timed-out runs are not restarted; the latter
+                // would most likely hang again
+                if reason == Some(TerminationReason::Stop) {
+                    agent.status = AgentStatus::Stopped;
+                } else if reason == Some(TerminationReason::Timeout) {
+                    agent.status = AgentStatus::TimedOut;
+                } else if agent.restart_policy.should_restart(succeeded)
+                    && agent.restart_count < agent.max_restarts
+                {
+                    let backoff = restart_backoff(agent.restart_backoff_ms, agent.restart_count);
+                    agent.status = AgentStatus::Restarting;
+                    self.pending_restarts.push((id, now + backoff));
+                } else if succeeded {
+                    agent.status = AgentStatus::Stopped;
+                } else {
+                    agent.status = AgentStatus::Error(match exit_code {
+                        Some(code) => format!("Exited with code {}", code),
+                        None => "Terminated by signal".to_string(),
+                    });
+                }
+            }
+        }
+
+        self.run_watchdog(now);
+
+        // Launch restarts whose backoff has elapsed
+        let (due, waiting): (Vec<_>, Vec<_>) = self
+            .pending_restarts
+            .drain(..)
+            .partition(|(_, at)| *at <= now);
+        self.pending_restarts = waiting;
+        for (id, _) in due {
+            if let Some(agent) = self.agents.iter_mut().find(|a| a.id == id) {
+                agent.restart_count += 1;
+            }
+            // A failed relaunch leaves the agent in the Error state
+            let _ = self.launch_agent(&id);
+        }
+
+        // Fill slots freed up by finished agents
+        while !self.at_capacity() {
+            let Some(id) = self.launch_queue.pop_front() else {
+                break;
+            };
+            let _ = self.launch_agent(&id);
+        }
+
+        self.resolve_waiting();
+    }
+
+    /// Completes the run's report from a background thread once its output
+    /// has been drained, so a slow pipe doesn't hold up status updates.
+    fn finish_report(&mut self, process: AgentProcess, exit_code: Option<i32>, reason: Option<TerminationReason>) {
+        let status = match reason {
+            Some(TerminationReason::Stop) => ReportStatus::Cancelled,
+            Some(TerminationReason::Timeout) => ReportStatus::TimedOut,
+            None if exit_code == Some(0) => ReportStatus::Success,
+            None => ReportStatus::Failed,
+        };
+        let report_window = self.report_window.clone();
+
+        self.report_finishers.retain(|finisher| !finisher.is_finished());
+        self.report_finishers.push(thread::spawn(move || {
+            let drain_deadline = Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+            for _ in 0..process.reader_count {
+                let remaining = drain_deadline.saturating_duration_since(Instant::now());
+                if process.readers_done.recv_timeout(remaining).is_err() {
+                    break;
+                }
+            }
+
+            let capture = process.capture.lock().unwrap();
+            let mut output = capture.output.clone();
+            match exit_code {
+                Some(0) => {}
+                Some(code) => output.push_str(&format!("[exited with code {}]\n", code)),
+                None => output.push_str("[terminated by signal]\n"),
+            }
+
+            let mut report_window = report_window.lock().unwrap();
+            report_window.set_metrics(&process.report_id, capture.metrics());
+            report_window.update_report(&process.report_id, status, output);
+        }));
+    }
+
+    /// Sends SIGTERM to agents that outlived the timeout and kills the
+    /// process group of any signalled agent still around after the grace
+    /// period.
+    fn run_watchdog(&mut self, now: Instant) {
+        let mut processes = self.processes.lock().unwrap();
+
+        if let Some(timeout) = self.timeout {
+            for process in processes.iter_mut() {
+                let already_signalled = self.terminations.iter().any(|t| t.pid == process.child.id());
+                if already_signalled || now.duration_since(process.started) < timeout {
+                    continue;
+                }
+                if send_stop_signal(&mut process.child, StopSignal::Terminate).is_ok() {
+                    self.terminations.push(Termination {
+                        pid: process.child.id(),
+                        reason: TerminationReason::Timeout,
+                        deadline: now + self.stop_grace,
+                    });
+                    if let Some(agent) = self.agents.iter_mut().find(|a| a.id == process.agent_id) {
+                        agent.status = AgentStatus::Stopping;
+                    }
+                }
             }
         }
+
+        for termination in &self.terminations {
+            if now < termination.deadline {
+                continue;
+            }
+            if let Some(process) = processes.iter_mut().find(|p| p.child.id() == termination.pid) {
+                let _ = kill_process_group(&mut process.child);
+            }
+        }
+    }
+}
+
+/// Reports a file that failed to load and renames it to
+/// `<name>.corrupt-<unix time>`, so the next save doesn't destroy it.
+pub(crate) fn set_aside_unreadable(path: &Path, error: &anyhow::Error) {
+    match back_up(path) {
+        Ok(backup) => eprintln!(
+            "Could not load {}: {}. Moved it to {}",
+            path.display(),
+            error,
+            backup.display()
+        ),
+        Err(backup_error) => eprintln!(
+            "Could not load {}: {}. Backing it up failed: {}",
+            path.display(),
+            error,
+            backup_error
+        ),
     }
 }
 
+fn back_up(path: &Path) -> anyhow::Result<PathBuf> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(
+        ".corrupt-{}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()
+    ));
+    let backup = PathBuf::from(backup);
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+/// Exponential backoff: `base * 2^attempt`, capped at `MAX_RESTART_BACKOFF`.
+fn restart_backoff(base_ms: u64, attempt: u32) -> Duration {
+    let factor = 1u64.checked_shl(attempt).unwrap_or(u64::MAX);
+    Duration::from_millis(base_ms.saturating_mul(factor)).min(MAX_RESTART_BACKOFF)
+}
+
 impl Default for AgentManager {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("synapse-agents-{}-{}-{}", name, std::process::id(), nanos))
+    }
+
+    /// Polls the manager until nothing is active any more, failing the test
+    /// if that takes longer than a few seconds.
+    fn run_until_idle(manager: &mut AgentManager) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while manager.has_active_agents() {
+            assert!(Instant::now() < deadline, "agents still active: {:?}", manager.get_agents());
+            manager.update_agent_status();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn agent_without_stdin_reads_end_of_file() {
+        let path = temp_path("stdin");
+        let mut manager = AgentManager::with_path(path.clone());
+        let agent = manager.add_agent("cat".to_string(), "cat".to_string(), Vec::new()).unwrap();
+
+        manager.start_agent(&agent.id).unwrap();
+        run_until_idle(&mut manager);
+        assert_eq!(manager.get_agent(&agent.id).unwrap().last_exit_code, Some(0));
+        manager.shutdown();
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn auto_start_waits_for_dependencies() {
+        let path = temp_path("auto-start");
+        let marker = temp_path("built");
+        let mut manager = AgentManager::with_path(path.clone());
+        // Registered before its dependency so registration order would be wrong
+        let tests = manager
+            .add_agent(
+                "tests".to_string(),
+                "test".to_string(),
+                vec!["-f".to_string(), marker.display().to_string()],
+            )
+            .unwrap();
+        let build = manager
+            .add_agent(
+                "build".to_string(),
+                "sh".to_string(),
+                vec!["-c".to_string(), format!("sleep 0.2 && touch '{}'", marker.display())],
+            )
+            .unwrap();
+        manager.set_auto_start(&tests.id, true).unwrap();
+        manager.set_auto_start(&build.id, true).unwrap();
+        manager
+            .add_dependency(&tests.id, &build.id, DependencyCondition::Succeeded)
+            .unwrap();
+
+        let mut settings = crate::settings::AppSettings::default().agents;
+        settings.auto_start = true;
+        assert!(manager.start_auto_agents(&settings).unwrap().is_empty());
+        assert_eq!(manager.get_agent(&build.id).unwrap().status, AgentStatus::Running);
+        assert_eq!(manager.get_agent(&tests.id).unwrap().status, AgentStatus::Waiting);
+
+        run_until_idle(&mut manager);
+        assert_eq!(manager.get_agent(&build.id).unwrap().last_exit_code, Some(0));
+        assert_eq!(manager.get_agent(&tests.id).unwrap().last_exit_code, Some(0));
+        assert_eq!(manager.get_agent(&tests.id).unwrap().status, AgentStatus::Stopped);
+        manager.shutdown();
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(marker);
+    }
+}
@@ -1,8 +1,9 @@
 // use gpui::*; // Commented out for CLI version
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::process::{Command, Stdio};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
 use tokio::process::Command as AsyncCommand;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +28,81 @@ pub enum AICLIType {
     Custom(String),
 }
 
+impl AICLIType {
+    pub fn from_tool_id(id: &str) -> Self {
+        match id {
+            "aider" => AICLIType::Aider,
+            "aider-pro" => AICLIType::AiderPro,
+            "gpt-pilot" => AICLIType::GPTPilot,
+            "cursor-cli" => AICLIType::CursorCLI,
+            "continue" => AICLIType::Continue,
+            other => AICLIType::Custom(other.to_string()),
+        }
+    }
+
+    /// Built-in parser for the usage lines this tool prints, if it prints any.
+    pub fn usage_parser(&self) -> Option<Box<dyn UsageParser>> {
+        match self {
+            AICLIType::Aider | AICLIType::AiderPro => Some(Box::new(AiderUsageParser)),
+            _ => None,
+        }
+    }
+}
+
+/// Token usage reported by a tool for one request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageUpdate {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub api_calls: u64,
+    /// Total cost of the session so far, when the tool reports it.
+    pub session_cost: Option<f64>,
+}
+
+/// Extracts usage from a tool's output, one line at a time.
+pub trait UsageParser: Send {
+    fn parse_line(&mut self, line: &str) -> Option<UsageUpdate>;
+}
+
+pub type UsageParserFactory = fn() -> Box<dyn UsageParser>;
+
+/// Parses Aider's per-message summary, e.g.
+/// `Tokens: 4.2k sent, 1.1k received. Cost: $0.03 message, $0.12 session.`
+pub struct AiderUsageParser;
+
+impl UsageParser for AiderUsageParser {
+    fn parse_line(&mut self, line: &str) -> Option<UsageUpdate> {
+        static TOKENS: OnceLock<Regex> = OnceLock::new();
+        static COST: OnceLock<Regex> = OnceLock::new();
+        let tokens = TOKENS.get_or_init(|| {
+            Regex::new(r"Tokens:\s*([\d.,]+[kKmM]?)\s+sent\b.*?([\d.,]+[kKmM]?)\s+received").unwrap()
+        });
+        let cost = COST.get_or_init(|| Regex::new(r"\$\s*([\d.,]+)\s+session").unwrap());
+
+        let captures = tokens.captures(line)?;
+        Some(UsageUpdate {
+            input_tokens: parse_token_count(&captures[1])?,
+            output_tokens: parse_token_count(&captures[2])?,
+            api_calls: 1,
+            session_cost: cost
+                .captures(line)
+                .and_then(|c| c[1].replace(',', "").parse().ok()),
+        })
+    }
+}
+
+/// Parses counts like `1,234`, `4.2k` or `1.5M`.
+fn parse_token_count(text: &str) -> Option<u64> {
+    let text = text.replace(',', "");
+    let (number, multiplier) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1_000.0),
+        'm' | 'M' => (&text[..text.len() - 1], 1_000_000.0),
+        _ => (text.as_str(), 1.0),
+    };
+    let value: f64 = number.parse().ok()?;
+    Some((value * multiplier).round() as u64)
+}
+
 pub struct AICLIManager {
     tools: Vec<AICLITool>,
     active_connections: Arc<Mutex<HashMap<String, tokio::process::Child>>>,
@@ -180,3 +256,60 @@ impl Default for AICLIManager {
 
 // UI Panel commented out for CLI version
 // All UI rendering code has been removed for CLI compilation
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<UsageUpdate> {
+        AiderUsageParser.parse_line(line)
+    }
+
+    fn usage(input_tokens: u64, output_tokens: u64, session_cost: Option<f64>) -> Option<UsageUpdate> {
+        Some(UsageUpdate {
+            input_tokens,
+            output_tokens,
+            api_calls: 1,
+            session_cost,
+        })
+    }
+
+    #[test]
+    fn aider_summary_with_cost() {
+        assert_eq!(
+            parse("Tokens: 2.5k sent, 249 received. Cost: $0.01 message, $0.05 session."),
+            usage(2_500, 249, Some(0.05))
+        );
+    }
+
+    #[test]
+    fn aider_summary_without_cost() {
+        assert_eq!(parse("Tokens: 1.2M sent, 3.4k received."), usage(1_200_000, 3_400, None));
+    }
+
+    #[test]
+    fn aider_summary_with_thousands_separators() {
+        assert_eq!(
+            parse("Tokens: 12,345 sent, 1,024 received. Cost: $1,234.50 message, $2,345.75 session."),
+            usage(12_345, 1_024, Some(2_345.75))
+        );
+    }
+
+    #[test]
+    fn aider_summary_with_prompt_cache() {
+        assert_eq!(
+            parse("Tokens: 12k sent, 2.1k cache write, 1.3k cache hit, 341 received. Cost: $0.06 message, $0.19 session."),
+            usage(12_000, 341, Some(0.19))
+        );
+        assert_eq!(
+            parse("Tokens: 8.7k sent, 8.1k cache hit, 95 received."),
+            usage(8_700, 95, None)
+        );
+    }
+
+    #[test]
+    fn other_lines_are_ignored() {
+        assert_eq!(parse("Added src/main.rs to the chat."), None);
+        assert_eq!(parse("Cost: $0.01 message, $0.05 session."), None);
+    }
+}
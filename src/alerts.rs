@@ -0,0 +1,639 @@
+use crate::accessibility::AccessibilityManager;
+use crate::agent::{self, AgentManager};
+use crate::logs::{field, LogEntry, LogFilter, LogLevel, LogPanel, LogSubscription};
+use crate::scripting::{ScriptContext, ScriptEngine};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Log entries buffered between two calls to `AlertManager::process`.
+const ALERT_QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub condition: AlertCondition,
+    pub actions: Vec<AlertAction>,
+    /// Matches are ignored for this long after the rule fires.
+    #[serde(default)]
+    pub cooldown_seconds: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Matches log entries at or above `min_level`, from a source containing
+/// `source`, whose message matches `pattern`. The rule fires once `count`
+/// such entries were logged within `window_seconds`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertCondition {
+    #[serde(default)]
+    pub min_level: Option<LogLevel>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default = "default_count")]
+    pub count: usize,
+    #[serde(default = "default_window_seconds")]
+    pub window_seconds: u64,
+}
+
+fn default_count() -> usize {
+    1
+}
+
+fn default_window_seconds() -> u64 {
+    60
+}
+
+impl Default for AlertCondition {
+    fn default() -> Self {
+        Self {
+            min_level: None,
+            source: None,
+            pattern: None,
+            count: default_count(),
+            window_seconds: default_window_seconds(),
+        }
+    }
+}
+
+/// Text fields may use `{rule}`, `{source}`, `{level}` and `{message}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AlertAction {
+    /// Stops `agent_id`, or the agent that logged the entry if unset.
+    StopAgent { agent_id: Option<String> },
+    RunScript { script_id: String },
+    Speak { text: String },
+    StatusMessage { text: String, level: LogLevel },
+}
+
+/// A message for the status bar raised by an alert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusMessage {
+    pub text: String,
+    pub level: LogLevel,
+}
+
+/// A rule that fired, with the entry that tipped it over and any actions
+/// that failed.
+#[derive(Debug, Clone)]
+pub struct AlertFiring {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub entry: LogEntry,
+    pub errors: Vec<String>,
+}
+
+/// What alert actions act on. Actions whose target is missing fail.
+#[derive(Default)]
+pub struct AlertTargets<'a> {
+    pub agents: Option<&'a mut AgentManager>,
+    pub scripts: Option<&'a mut ScriptEngine>,
+    pub accessibility: Option<&'a AccessibilityManager>,
+}
+
+struct RuleState {
+    /// `None` if the rule has no pattern.
+    regex: Option<Regex>,
+    /// Set when a stored pattern doesn't compile; the rule never matches.
+    invalid: bool,
+    hits: VecDeque<SystemTime>,
+    last_fired: Option<SystemTime>,
+}
+
+impl RuleState {
+    fn new(rule: &AlertRule) -> anyhow::Result<Self> {
+        let regex = match rule.condition.pattern {
+            Some(ref pattern) => Some(Regex::new(pattern)?),
+            None => None,
+        };
+        Ok(Self {
+            regex,
+            invalid: false,
+            hits: VecDeque::new(),
+            last_fired: None,
+        })
+    }
+
+    fn matches(&self, condition: &AlertCondition, entry: &LogEntry) -> bool {
+        if self.invalid {
+            return false;
+        }
+        if condition.min_level.as_ref().is_some_and(|min| entry.level < *min) {
+            return false;
+        }
+        if condition
+            .source
+            .as_ref()
+            .is_some_and(|source| !entry.source.contains(source.as_str()))
+        {
+            return false;
+        }
+        self.regex.as_ref().is_none_or(|regex| regex.is_match(&entry.message))
+    }
+
+    /// Records a match and reports whether the rate threshold is reached.
+    /// Firing resets the window, so the rule needs `count` new matches after
+    /// its cooldown to fire again.
+    fn record_hit(&mut self, rule: &AlertRule, time: SystemTime) -> bool {
+        let cooldown = Duration::from_secs(rule.cooldown_seconds);
+        if self
+            .last_fired
+            .is_some_and(|fired| time.duration_since(fired).unwrap_or_default() < cooldown)
+        {
+            return false;
+        }
+
+        let condition = &rule.condition;
+        let window = Duration::from_secs(condition.window_seconds);
+        while self
+            .hits
+            .front()
+            .is_some_and(|hit| time.duration_since(*hit).unwrap_or_default() > window)
+        {
+            self.hits.pop_front();
+        }
+        self.hits.push_back(time);
+        if self.hits.len() >= condition.count.max(1) {
+            self.hits.clear();
+            self.last_fired = Some(time);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct AlertManager {
+    rules: Vec<AlertRule>,
+    states: Vec<RuleState>,
+    rules_path: PathBuf,
+    subscription: Option<LogSubscription>,
+    status_messages: Vec<StatusMessage>,
+}
+
+impl AlertManager {
+    pub fn new() -> Self {
+        Self::with_path(Self::get_rules_path())
+    }
+
+    pub fn with_path(rules_path: PathBuf) -> Self {
+        let rules = Self::load_rules(&rules_path).unwrap_or_else(|e| {
+            agent::set_aside_unreadable(&rules_path, &e);
+            Vec::new()
+        });
+        let states = rules
+            .iter()
+            .map(|rule| {
+                RuleState::new(rule).unwrap_or_else(|e| {
+                    eprintln!("Alert rule '{}' is inactive, its pattern is invalid: {}", rule.name, e);
+                    RuleState {
+                        regex: None,
+                        invalid: true,
+                        hits: VecDeque::new(),
+                        last_fired: None,
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            rules,
+            states,
+            rules_path,
+            subscription: None,
+            status_messages: Vec::new(),
+        }
+    }
+
+    fn get_rules_path() -> PathBuf {
+        if let Some(home) = dirs::home_dir() {
+            home.join(".synapse").join("alerts.json")
+        } else {
+            PathBuf::from("./synapse_alerts.json")
+        }
+    }
+
+    fn load_rules(path: &PathBuf) -> anyhow::Result<Vec<AlertRule>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.rules_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(&self.rules)?;
+        fs::write(&self.rules_path, json)?;
+        Ok(())
+    }
+
+    /// Starts watching everything logged to `panel`.
+    pub fn attach(&mut self, panel: &mut LogPanel) {
+        self.subscription = Some(panel.subscribe(LogFilter::default(), ALERT_QUEUE_CAPACITY));
+    }
+
+    /// Adds a rule, failing if its pattern is not a valid regex. The rule's
+    /// id is generated.
+    pub fn add_rule(&mut self, mut rule: AlertRule) -> anyhow::Result<AlertRule> {
+        let state = RuleState::new(&rule)?;
+        rule.id = format!(
+            "rule_{}",
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+        );
+        self.rules.push(rule.clone());
+        self.states.push(state);
+        if let Err(e) = self.save() {
+            self.rules.pop();
+            self.states.pop();
+            return Err(e);
+        }
+        Ok(rule)
+    }
+
+    pub fn remove_rule(&mut self, id: &str) -> anyhow::Result<()> {
+        let index = self
+            .rules
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Alert rule not found"))?;
+        self.rules.remove(index);
+        self.states.remove(index);
+        self.save()
+    }
+
+    pub fn set_enabled(&mut self, id: &str, enabled: bool) -> anyhow::Result<()> {
+        let rule = self
+            .rules
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Alert rule not found"))?;
+        rule.enabled = enabled;
+        self.save()
+    }
+
+    pub fn get_rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Status messages raised since the last call.
+    pub fn take_status_messages(&mut self) -> Vec<StatusMessage> {
+        std::mem::take(&mut self.status_messages)
+    }
+
+    /// Evaluates the entries logged since the last call and runs the actions
+    /// of every rule that fires.
+    pub fn process(&mut self, targets: &mut AlertTargets) -> Vec<AlertFiring> {
+        let entries: Vec<LogEntry> = match self.subscription {
+            Some(ref subscription) => subscription.receiver.try_iter().collect(),
+            None => return Vec::new(),
+        };
+        entries
+            .iter()
+            .flat_map(|entry| self.evaluate(entry, targets))
+            .collect()
+    }
+
+    /// Evaluates a single entry against every enabled rule.
+    pub fn evaluate(&mut self, entry: &LogEntry, targets: &mut AlertTargets) -> Vec<AlertFiring> {
+        let mut fired = Vec::new();
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            if rule.enabled
+                && state.matches(&rule.condition, entry)
+                && state.record_hit(rule, entry.time())
+            {
+                fired.push(rule.clone());
+            }
+        }
+
+        fired
+            .into_iter()
+            .map(|rule| {
+                let errors = rule
+                    .actions
+                    .iter()
+                    .filter_map(|action| self.run_action(&rule, action, entry, targets).err())
+                    .map(|e| e.to_string())
+                    .collect();
+                AlertFiring {
+                    rule_id: rule.id,
+                    rule_name: rule.name,
+                    entry: entry.clone(),
+                    errors,
+                }
+            })
+            .collect()
+    }
+
+    fn run_action(
+        &mut self,
+        rule: &AlertRule,
+        action: &AlertAction,
+        entry: &LogEntry,
+        targets: &mut AlertTargets,
+    ) -> anyhow::Result<()> {
+        match action {
+            AlertAction::StopAgent { agent_id } => {
+                let agent_id = agent_id
+                    .as_deref()
+                    .or(entry.field(field::AGENT_ID))
+                    .ok_or_else(|| anyhow::anyhow!("Log entry has no agent to stop"))?;
+                targets
+                    .agents
+                    .as_deref_mut()
+                    .ok_or_else(|| anyhow::anyhow!("No agent manager to stop agents with"))?
+                    .stop_agent(agent_id)
+            }
+            AlertAction::RunScript { script_id } => {
+                let mut context = ScriptContext {
+                    agent_id: entry.field(field::AGENT_ID).map(str::to_string),
+                    terminal_id: entry.field(field::TERMINAL_ID).map(str::to_string),
+                    ..ScriptContext::default()
+                };
+                context.variables.insert("rule".to_string(), rule.name.clone());
+                context.variables.insert("source".to_string(), entry.source.clone());
+                context.variables.insert("level".to_string(), entry.level.as_str().to_string());
+                context.variables.insert("message".to_string(), entry.message.clone());
+                targets
+                    .scripts
+                    .as_deref_mut()
+                    .ok_or_else(|| anyhow::anyhow!("No script engine to run scripts with"))?
+                    .execute_script(script_id, &context)
+                    .map(|_| ())
+            }
+            AlertAction::Speak { text } => targets
+                .accessibility
+                .ok_or_else(|| anyhow::anyhow!("No accessibility manager to speak with"))?
+                .speak(&expand(text, rule, entry)),
+            AlertAction::StatusMessage { text, level } => {
+                self.status_messages.push(StatusMessage {
+                    text: expand(text, rule, entry),
+                    level: level.clone(),
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+fn expand(text: &str, rule: &AlertRule, entry: &LogEntry) -> String {
+    text.replace("{rule}", &rule.name)
+        .replace("{source}", &entry.source)
+        .replace("{level}", entry.level.as_str())
+        .replace("{message}", &entry.message)
+}
+
+impl Default for AlertManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentStatus;
+    use crate::scripting::{Script, ScriptLanguage};
+    use std::collections::BTreeMap;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("synapse-alerts-{}-{}-{}", name, std::process::id(), nanos))
+    }
+
+    fn rule(pattern: &str, actions: Vec<AlertAction>, cooldown_seconds: u64) -> AlertRule {
+        AlertRule {
+            id: String::new(),
+            name: "rate limit".to_string(),
+            enabled: true,
+            condition: AlertCondition {
+                min_level: Some(LogLevel::Error),
+                pattern: Some(pattern.to_string()),
+                ..AlertCondition::default()
+            },
+            actions,
+            cooldown_seconds,
+        }
+    }
+
+    fn entry(message: &str) -> LogEntry {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        LogEntry {
+            timestamp: now.as_secs(),
+            timestamp_ms: now.as_millis() as u64,
+            level: LogLevel::Error,
+            source: "agent".to_string(),
+            message: message.to_string(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Feeds a matching line twice within the cooldown, plus a non-matching
+    /// one, through a log panel and returns the firings.
+    fn fire(manager: &mut AlertManager, targets: &mut AlertTargets, agent_id: Option<&str>) -> Vec<AlertFiring> {
+        let mut panel = LogPanel::new(&mut ());
+        manager.attach(&mut panel);
+        let metadata: BTreeMap<String, String> = agent_id
+            .map(|id| (field::AGENT_ID.to_string(), id.to_string()))
+            .into_iter()
+            .collect();
+        for (level, message) in [
+            (LogLevel::Error, "429 rate limited"),
+            (LogLevel::Info, "429 rate limited"),
+            (LogLevel::Error, "429 rate limited again"),
+        ] {
+            panel.log_with_fields(level, "agent", message.to_string(), metadata.clone());
+        }
+        manager.process(targets)
+    }
+
+    #[test]
+    fn status_message_is_posted_once_per_cooldown() {
+        let path = temp_path("status");
+        let mut manager = AlertManager::with_path(path.clone());
+        let action = AlertAction::StatusMessage {
+            text: "{rule}: {message}".to_string(),
+            level: LogLevel::Warn,
+        };
+        manager.add_rule(rule("429", vec![action], 60)).unwrap();
+
+        let firings = fire(&mut manager, &mut AlertTargets::default(), None);
+        assert_eq!(firings.len(), 1);
+        assert!(firings[0].errors.is_empty());
+        assert_eq!(
+            manager.take_status_messages(),
+            vec![StatusMessage {
+                text: "rate limit: 429 rate limited".to_string(),
+                level: LogLevel::Warn,
+            }]
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn rule_fires_again_once_the_cooldown_is_over() {
+        let path = temp_path("cooldown");
+        let mut manager = AlertManager::with_path(path.clone());
+        let action = AlertAction::StatusMessage {
+            text: "{message}".to_string(),
+            level: LogLevel::Warn,
+        };
+        manager.add_rule(rule("429", vec![action], 60)).unwrap();
+
+        let first = entry("429 first");
+        let mut within = entry("429 within");
+        within.timestamp_ms = first.timestamp_ms + 59_000;
+        let mut after = entry("429 after");
+        after.timestamp_ms = first.timestamp_ms + 60_000;
+
+        let mut targets = AlertTargets::default();
+        for entry in [&first, &within, &after] {
+            manager.evaluate(entry, &mut targets);
+        }
+        let texts: Vec<String> = manager.take_status_messages().into_iter().map(|m| m.text).collect();
+        assert_eq!(texts, vec!["429 first".to_string(), "429 after".to_string()]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn script_runs_once_per_cooldown() {
+        let path = temp_path("script");
+        let marker = temp_path("script-marker");
+        let mut scripts = ScriptEngine::new();
+        scripts.add_script(Script {
+            id: "notify".to_string(),
+            name: "Notify".to_string(),
+            language: ScriptLanguage::Shell,
+            code: format!("echo ran >> '{}'", marker.display()),
+            description: String::new(),
+            enabled: true,
+        });
+        let mut manager = AlertManager::with_path(path.clone());
+        let action = AlertAction::RunScript {
+            script_id: "notify".to_string(),
+        };
+        manager.add_rule(rule("429", vec![action], 60)).unwrap();
+
+        let mut targets = AlertTargets {
+            scripts: Some(&mut scripts),
+            ..AlertTargets::default()
+        };
+        let firings = fire(&mut manager, &mut targets, None);
+        assert_eq!(firings.len(), 1);
+        assert!(firings[0].errors.is_empty());
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "ran\n");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(marker);
+    }
+
+    #[test]
+    fn speech_is_requested_once_per_cooldown() {
+        let path = temp_path("speak");
+        let accessibility = AccessibilityManager::new();
+        let mut manager = AlertManager::with_path(path.clone());
+        let action = AlertAction::Speak {
+            text: "{rule}".to_string(),
+        };
+        manager.add_rule(rule("429", vec![action], 60)).unwrap();
+
+        let mut targets = AlertTargets {
+            accessibility: Some(&accessibility),
+            ..AlertTargets::default()
+        };
+        let firings = fire(&mut manager, &mut targets, None);
+        assert_eq!(firings.len(), 1);
+        assert!(firings[0].errors.is_empty());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn unreadable_rules_file_is_set_aside() {
+        let path = temp_path("corrupt");
+        fs::write(&path, "[{\"id\": ").unwrap();
+        let mut manager = AlertManager::with_path(path.clone());
+        assert!(manager.get_rules().is_empty());
+        assert!(!path.exists());
+
+        let backup = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|p| p.to_string_lossy().starts_with(&format!("{}.corrupt-", path.display())))
+            .expect("backup of the unreadable file");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "[{\"id\": ");
+
+        manager.add_rule(rule("429", Vec::new(), 0)).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "[{\"id\": ");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+    }
+
+    #[test]
+    fn rule_is_not_added_when_saving_fails() {
+        // A file where the rules' directory should be makes saving fail
+        let blocker = temp_path("blocker");
+        fs::write(&blocker, "").unwrap();
+        let mut manager = AlertManager::with_path(blocker.join("alerts.json"));
+
+        assert!(manager.add_rule(rule("429", Vec::new(), 0)).is_err());
+        assert!(manager.get_rules().is_empty());
+        assert!(manager.evaluate(&entry("429"), &mut AlertTargets::default()).is_empty());
+        let _ = fs::remove_file(blocker);
+    }
+
+    #[test]
+    fn rule_with_invalid_stored_pattern_is_kept_but_never_fires() {
+        let path = temp_path("invalid");
+        let mut stored = rule("(unclosed", Vec::new(), 0);
+        stored.id = "rule_1".to_string();
+        fs::write(&path, serde_json::to_string(&vec![stored]).unwrap()).unwrap();
+
+        let mut manager = AlertManager::with_path(path.clone());
+        assert_eq!(manager.get_rules().len(), 1);
+        assert!(manager.evaluate(&entry("(unclosed"), &mut AlertTargets::default()).is_empty());
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn agent_is_stopped_once_per_cooldown() {
+        let agents_path = temp_path("agents");
+        let path = temp_path("stop");
+        let mut agents = AgentManager::with_path(agents_path.clone());
+        let agent = agents
+            .add_agent("sleeper".to_string(), "sleep".to_string(), vec!["30".to_string()])
+            .unwrap();
+        agents.start_agent(&agent.id).unwrap();
+
+        let mut manager = AlertManager::with_path(path.clone());
+        let action = AlertAction::StopAgent { agent_id: None };
+        manager.add_rule(rule("429", vec![action], 60)).unwrap();
+
+        let firings = {
+            let mut targets = AlertTargets {
+                agents: Some(&mut agents),
+                ..AlertTargets::default()
+            };
+            fire(&mut manager, &mut targets, Some(&agent.id))
+        };
+        assert_eq!(firings.len(), 1);
+        assert!(firings[0].errors.is_empty());
+        assert_eq!(agents.get_agent(&agent.id).unwrap().status, AgentStatus::Stopping);
+
+        agents.shutdown();
+        assert_eq!(agents.get_agent(&agent.id).unwrap().status, AgentStatus::Stopped);
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(agents_path);
+    }
+}
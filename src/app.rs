@@ -2,7 +2,7 @@ use crate::actions::*;
 use crate::agent::AgentManager;
 use crate::grid::Grid;
 use crate::lens::Lens;
-use crate::logs::LogPanel;
+use crate::logs::{LogLevel, LogPanel};
 use crate::report::ReportWindow;
 use crate::roster::Roster;
 use crate::settings::SettingsManager;
@@ -88,10 +88,12 @@ impl SynapseApp {
         let status_bar = cx.new_view(|cx| StatusBar::new(cx));
         let agent_manager = AgentManager::new();
 
-        cx.subscribe_global_action(move |this: &mut Self, action: &NewTerminal, cx: &mut WindowContext| {
-            this.grid.update(cx, |grid, cx| {
-                grid.add_terminal(cx);
-            });
+        cx.subscribe_global_action(|this: &mut Self, action: &NewTerminal, cx: &mut WindowContext| {
+            if let Err(e) = this.open_terminal(action.profile.as_deref(), cx) {
+                this.log_panel.update(cx, |panel, _cx| {
+                    panel.log(LogLevel::Error, "terminal", e.to_string());
+                });
+            }
         });
 
         cx.new_view(|_cx| Self {
@@ -111,6 +113,22 @@ impl SynapseApp {
             active_tab: ActiveTab::Grid,
         })
     }
+
+    /// Opens a terminal with the named profile, or the default one, as the
+    /// settings on disk describe it now.
+    fn open_terminal(&mut self, profile: Option<&str>, cx: &mut WindowContext) -> anyhow::Result<()> {
+        self.settings_manager.reload();
+        let terminal = &self.settings_manager.get().terminal;
+        let profile = match profile {
+            Some(name) => terminal
+                .profile(name)
+                .ok_or_else(|| anyhow::anyhow!("No terminal profile named {}", name))?,
+            None => terminal.default_profile(),
+        };
+        self.grid
+            .update(cx, |grid, cx| grid.add_terminal(&profile, cx))
+            .map_err(|e| anyhow::anyhow!("Could not open terminal profile {}: {}", profile.name, e))
+    }
 }
 
 impl Render for SynapseApp {
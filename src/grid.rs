@@ -1,3 +1,4 @@
+use crate::settings::TerminalProfile;
 use crate::terminal::Terminal;
 use gpui::*;
 
@@ -22,11 +23,12 @@ impl Grid {
         }
     }
 
-    pub fn add_terminal(&mut self, cx: &mut ViewContext<Self>) {
-        let terminal = cx.new_view(|_cx| Terminal::new());
-        self.terminals.push(terminal);
+    pub fn add_terminal(&mut self, profile: &TerminalProfile, cx: &mut ViewContext<Self>) -> anyhow::Result<()> {
+        let terminal = Terminal::new(profile)?;
+        self.terminals.push(cx.new_view(|_cx| terminal));
         self.update_layout();
         cx.notify();
+        Ok(())
     }
 
     fn update_layout(&mut self) {
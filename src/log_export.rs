@@ -0,0 +1,407 @@
+use crate::logs::{self, LogEntry, LogLevel};
+use crate::settings::{LogFormat, LogSettings, LogSink, LogTimezone};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// While a forwarding sink keeps failing, this is how often it is reported.
+const FORWARD_ERROR_INTERVAL: Duration = Duration::from_secs(60);
+
+impl LogFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            LogFormat::JsonLines => "jsonl",
+            LogFormat::Text => "log",
+            LogFormat::Syslog => "syslog",
+        }
+    }
+}
+
+/// Renders log entries as single lines in one of the `LogFormat`s.
+pub struct LogFormatter {
+    format: LogFormat,
+    template: String,
+    timestamp_format: String,
+    timezone: LogTimezone,
+    app_name: String,
+    hostname: String,
+}
+
+impl LogFormatter {
+    pub fn new(format: LogFormat, settings: &LogSettings) -> Self {
+        Self {
+            format,
+            template: settings.text_template.clone(),
+            timestamp_format: settings.timestamp_format.clone(),
+            timezone: settings.timezone,
+            app_name: settings.app_name.clone(),
+            hostname: hostname(),
+        }
+    }
+
+    /// One line without the trailing newline.
+    pub fn format_entry(&self, entry: &LogEntry) -> String {
+        match self.format {
+            // Same shape as the log files, so exports can be read back
+            LogFormat::JsonLines => serde_json::to_string(entry).unwrap_or_default(),
+            LogFormat::Text => self.to_text(entry),
+            LogFormat::Syslog => self.to_syslog(entry),
+        }
+    }
+
+    fn to_text(&self, entry: &LogEntry) -> String {
+        let mut out = String::with_capacity(self.template.len() + entry.message.len());
+        let mut rest = self.template.as_str();
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+                // An unclosed brace is literal text
+                out.push_str(&rest[open..]);
+                return out;
+            };
+            let name = &rest[open + 1..close];
+            match name {
+                "timestamp" => out.push_str(&logs::format_time(entry.time(), &self.timestamp_format, self.timezone)),
+                "level" => out.push_str(entry.level.as_str()),
+                "source" => out.push_str(&single_line(&entry.source)),
+                "message" => out.push_str(&single_line(&entry.message)),
+                "fields" => {
+                    let fields: Vec<String> = entry
+                        .metadata
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", single_line(key), single_line(value)))
+                        .collect();
+                    out.push_str(&fields.join(" "));
+                }
+                _ => match name.strip_prefix("field.") {
+                    Some(key) => out.push_str(&single_line(entry.field(key).unwrap_or(""))),
+                    // Not a placeholder; keep it as written
+                    None => out.push_str(&rest[open..=close]),
+                },
+            }
+            rest = &rest[close + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG` with the
+    /// log source as MSGID and the metadata as structured data.
+    fn to_syslog(&self, entry: &LogEntry) -> String {
+        // Facility 1 (user-level messages)
+        let priority = 8 + syslog_severity(&entry.level);
+        let timestamp = logs::format_time(entry.time(), "rfc3339", LogTimezone::Utc);
+
+        let structured_data = if entry.metadata.is_empty() {
+            "-".to_string()
+        } else {
+            let mut sd = String::from("[synapse@32473");
+            for (key, value) in &entry.metadata {
+                sd.push_str(&format!(" {}=\"{}\"", sd_name(key), sd_escape(&single_line(value))));
+            }
+            sd.push(']');
+            sd
+        };
+
+        format!(
+            "<{}>1 {} {} {} {} {} {} {}",
+            priority,
+            timestamp,
+            header_field(&self.hostname, 255),
+            header_field(&self.app_name, 48),
+            std::process::id(),
+            header_field(&entry.source, 32),
+            structured_data,
+            single_line(&entry.message),
+        )
+    }
+}
+
+/// Escapes line breaks so a multi-line message stays one record.
+fn single_line(text: &str) -> Cow<'_, str> {
+    if !text.contains(['\n', '\r']) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.replace('\r', "\\r").replace('\n', "\\n"))
+}
+
+fn syslog_severity(level: &LogLevel) -> u8 {
+    match level {
+        LogLevel::Critical => 2,
+        LogLevel::Error => 3,
+        LogLevel::Warn => 4,
+        LogLevel::Info => 6,
+        LogLevel::Debug | LogLevel::Trace => 7,
+    }
+}
+
+/// Header fields are printable ASCII without spaces; `-` stands for empty.
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max_len)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// SD-PARAM names additionally exclude `=`, `]` and `"`.
+fn sd_name(key: &str) -> String {
+    key.chars()
+        .map(|c| match c {
+            '=' | ']' | '"' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .take(32)
+        .collect()
+}
+
+fn sd_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its full length
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+pub fn export_logs(entries: &[&LogEntry], formatter: &LogFormatter) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        out.push_str(&formatter.format_entry(entry));
+        out.push('\n');
+    }
+    out
+}
+
+enum SinkWriter {
+    #[cfg(unix)]
+    Syslog(std::os::unix::net::UnixDatagram),
+    File(File),
+}
+
+/// Writes each log entry to the configured sink as it is logged.
+pub struct LogForwarder {
+    formatter: LogFormatter,
+    sink: LogSink,
+    writer: SinkWriter,
+    /// Entries that could not be forwarded since the sink last worked.
+    dropped: usize,
+    last_error_notice: Option<Instant>,
+}
+
+impl LogForwarder {
+    pub fn new(settings: &LogSettings) -> anyhow::Result<Self> {
+        let sink = settings.forward.sink.clone();
+        Ok(Self {
+            formatter: LogFormatter::new(settings.forward.format, settings),
+            writer: Self::connect(&sink)?,
+            sink,
+            dropped: 0,
+            last_error_notice: None,
+        })
+    }
+
+    fn sink_name(&self) -> &str {
+        match self.sink {
+            LogSink::Syslog { ref socket } => socket,
+            LogSink::File { ref path } => path,
+        }
+    }
+
+    fn connect(sink: &LogSink) -> anyhow::Result<SinkWriter> {
+        match sink {
+            #[cfg(unix)]
+            LogSink::Syslog { socket } => {
+                let datagram = std::os::unix::net::UnixDatagram::unbound()?;
+                datagram.connect(socket)?;
+                Ok(SinkWriter::Syslog(datagram))
+            }
+            #[cfg(not(unix))]
+            LogSink::Syslog { .. } => Err(anyhow::anyhow!("Syslog sockets are only supported on Unix")),
+            LogSink::File { path } => Ok(SinkWriter::File(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+        }
+    }
+
+    pub fn forward(&mut self, entry: &LogEntry) -> anyhow::Result<()> {
+        let line = self.formatter.format_entry(entry);
+        match self.writer {
+            #[cfg(unix)]
+            SinkWriter::Syslog(ref socket) => {
+                if socket.send(line.as_bytes()).is_err() {
+                    // The syslog daemon may have restarted; reconnect once
+                    self.writer = Self::connect(&self.sink)?;
+                    if let SinkWriter::Syslog(ref socket) = self.writer {
+                        socket.send(line.as_bytes())?;
+                    }
+                }
+            }
+            SinkWriter::File(ref mut file) => writeln!(file, "{}", line)?,
+        }
+        Ok(())
+    }
+
+    /// Forwards `entry` and returns a notice for the user when the sink
+    /// fails, at most once per `FORWARD_ERROR_INTERVAL`, and when it works
+    /// again after that.
+    pub fn forward_or_notice(&mut self, entry: &LogEntry) -> Option<String> {
+        match self.forward(entry) {
+            Ok(()) => {
+                if self.dropped == 0 {
+                    return None;
+                }
+                let dropped = std::mem::take(&mut self.dropped);
+                self.last_error_notice = None;
+                Some(format!(
+                    "Log forwarding to {} works again, {} entries were not forwarded",
+                    self.sink_name(),
+                    dropped
+                ))
+            }
+            Err(e) => {
+                self.dropped += 1;
+                if self
+                    .last_error_notice
+                    .is_some_and(|last| last.elapsed() < FORWARD_ERROR_INTERVAL)
+                {
+                    return None;
+                }
+                self.last_error_notice = Some(Instant::now());
+                Some(format!(
+                    "Log forwarding to {} failed ({} entries not forwarded): {}",
+                    self.sink_name(),
+                    self.dropped,
+                    e
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_formatter(template: &str) -> LogFormatter {
+        let settings = LogSettings {
+            text_template: template.to_string(),
+            ..LogSettings::default()
+        };
+        LogFormatter::new(LogFormat::Text, &settings)
+    }
+
+    fn entry() -> LogEntry {
+        LogEntry {
+            timestamp: 1_700_000_000,
+            timestamp_ms: 1_700_000_000_000,
+            level: LogLevel::Warn,
+            source: "agent".to_string(),
+            message: "disk low".to_string(),
+            metadata: [("agent_id".to_string(), "a1".to_string())].into(),
+        }
+    }
+
+    #[test]
+    fn text_template_expands_placeholders() {
+        let formatter = text_formatter("{level} {source}: {message} ({field.agent_id}) {unknown}");
+        assert_eq!(formatter.format_entry(&entry()), "WARN agent: disk low (a1) {unknown}");
+    }
+
+    #[test]
+    fn unclosed_brace_is_kept_once() {
+        let formatter = text_formatter("{level} {message} {oops");
+        assert_eq!(formatter.format_entry(&entry()), "WARN disk low {oops");
+
+        let formatter = text_formatter("prefix {");
+        assert_eq!(formatter.format_entry(&entry()), "prefix {");
+    }
+
+    #[test]
+    fn line_breaks_are_escaped_in_text_and_syslog() {
+        let mut multi_line = entry();
+        multi_line.message = "panicked at main.rs:\r\n  disk low".to_string();
+        multi_line.metadata.insert("stack".to_string(), "a\nb".to_string());
+
+        let formatter = text_formatter("{message} {fields}");
+        assert_eq!(
+            formatter.format_entry(&multi_line),
+            "panicked at main.rs:\\r\\n  disk low agent_id=a1 stack=a\\nb"
+        );
+
+        let syslog = LogFormatter::new(LogFormat::Syslog, &LogSettings::default()).format_entry(&multi_line);
+        assert!(!syslog.contains(['\n', '\r']));
+        assert!(syslog.ends_with(" stack=\"a\\\\nb\"] panicked at main.rs:\\r\\n  disk low"));
+
+        // JSON Lines already escapes them
+        let json = LogFormatter::new(LogFormat::JsonLines, &LogSettings::default()).format_entry(&multi_line);
+        assert!(!json.contains('\n'));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_sink_is_reported_once_per_interval() {
+        use crate::settings::LogForwardSettings;
+        use std::os::unix::net::UnixDatagram;
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let socket = std::env::temp_dir().join(format!("synapse-syslog-{}-{}", std::process::id(), nanos));
+        let daemon = UnixDatagram::bind(&socket).unwrap();
+        let settings = LogSettings {
+            forward: LogForwardSettings {
+                enabled: true,
+                sink: LogSink::Syslog {
+                    socket: socket.display().to_string(),
+                },
+                format: LogFormat::Text,
+            },
+            ..LogSettings::default()
+        };
+        let mut forwarder = LogForwarder::new(&settings).unwrap();
+        assert_eq!(forwarder.forward_or_notice(&entry()), None);
+
+        // The daemon goes away for good
+        drop(daemon);
+        std::fs::remove_file(&socket).unwrap();
+        let notice = forwarder.forward_or_notice(&entry()).expect("first failure is reported");
+        assert!(notice.contains(&socket.display().to_string()));
+        assert_eq!(forwarder.forward_or_notice(&entry()), None);
+        assert_eq!(forwarder.forward_or_notice(&entry()), None);
+
+        // Once it is back, the gap is reported
+        let _daemon = UnixDatagram::bind(&socket).unwrap();
+        let notice = forwarder.forward_or_notice(&entry()).expect("recovery is reported");
+        assert!(notice.contains("3 entries were not forwarded"));
+        assert_eq!(forwarder.forward_or_notice(&entry()), None);
+        let _ = std::fs::remove_file(&socket);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::logs::{LogEntry, LogFilter};
+use crate::settings::LogSettings;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const CURRENT_FILE: &str = "synapse.jsonl";
+
+/// Log entries appended as JSON Lines to `~/.synapse/logs/synapse.jsonl`.
+/// When that file gets too large or too old it is renamed to
+/// `synapse-<UTC time>-<seq>.jsonl`, gzipped if configured, and a new one
+/// started.
+pub struct LogStore {
+    dir: PathBuf,
+    settings: LogSettings,
+    file: Option<File>,
+    /// Size of the current file and time of its first entry.
+    size: u64,
+    opened_at: Option<SystemTime>,
+}
+
+impl LogStore {
+    pub fn new(settings: LogSettings) -> Self {
+        Self::with_dir(Self::get_logs_dir(), settings)
+    }
+
+    pub fn with_dir(dir: PathBuf, settings: LogSettings) -> Self {
+        Self {
+            dir,
+            settings,
+            file: None,
+            size: 0,
+            opened_at: None,
+        }
+    }
+
+    fn get_logs_dir() -> PathBuf {
+        if let Some(home) = dirs::home_dir() {
+            home.join(".synapse").join("logs")
+        } else {
+            PathBuf::from("./synapse_logs")
+        }
+    }
+
+    fn current_path(&self) -> PathBuf {
+        self.dir.join(CURRENT_FILE)
+    }
+
+    /// Opens the current file, picking up the size and first entry of one
+    /// left behind by an earlier session.
+    fn open(&mut self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.current_path();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.size = file.metadata()?.len();
+        self.opened_at = read_lines(&path)?
+            .next()
+            .and_then(|line| serde_json::from_str::<LogEntry>(&line).ok())
+            .map(|entry| entry.time());
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn needs_rotation(&self, now: SystemTime) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_big = self.settings.max_file_bytes > 0 && self.size >= self.settings.max_file_bytes;
+        let max_age = Duration::from_secs(self.settings.max_file_age_hours * 3600);
+        let too_old = self.settings.max_file_age_hours > 0
+            && self
+                .opened_at
+                .and_then(|opened| now.duration_since(opened).ok())
+                .is_some_and(|age| age >= max_age);
+        too_big || too_old
+    }
+
+    pub fn append(&mut self, entry: &LogEntry) -> anyhow::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.needs_rotation(SystemTime::now()) {
+            self.rotate()?;
+        }
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        if let Some(ref mut file) = self.file {
+            file.write_all(line.as_bytes())?;
+        }
+        self.size += line.len() as u64;
+        self.opened_at.get_or_insert(entry.time());
+        Ok(())
+    }
+
+    /// Archives the current file and starts a new one, then deletes archives
+    /// past the retention period.
+    pub fn rotate(&mut self) -> anyhow::Result<()> {
+        self.file = None;
+        let current = self.current_path();
+        if current.exists() {
+            let archive = self.archive_path();
+            fs::rename(&current, &archive)?;
+            if self.settings.compress {
+                compress(&archive)?;
+            }
+        }
+        self.open()?;
+        self.prune()?;
+        Ok(())
+    }
+
+    /// A name for the next archive. The sequence number keeps names unique,
+    /// and sorted by age, when rotating several times within a millisecond.
+    fn archive_path(&self) -> PathBuf {
+        let stamp = DateTime::<Utc>::from(SystemTime::now()).format("%Y%m%d-%H%M%S%.3f");
+        let mut sequence = 0;
+        loop {
+            let path = self.dir.join(format!("synapse-{}-{:03}.jsonl", stamp, sequence));
+            if !path.exists() && !path.with_extension("jsonl.gz").exists() {
+                return path;
+            }
+            sequence += 1;
+        }
+    }
+
+    /// Deletes rotated files last written more than `retention_days` ago.
+    pub fn prune(&self) -> anyhow::Result<usize> {
+        if self.settings.retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = SystemTime::now() - Duration::from_secs(self.settings.retention_days * 24 * 3600);
+        let mut removed = 0;
+        for path in self.archives() {
+            if modified(&path).is_some_and(|time| time < cutoff) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Rotated files, oldest first. The UTC time in their names sorts
+    /// chronologically.
+    fn archives(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut archives: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.starts_with("synapse-")
+                            && (name.ends_with(".jsonl") || name.ends_with(".jsonl.gz"))
+                    })
+            })
+            .collect();
+        archives.sort();
+        archives
+    }
+
+    /// Scans the archives and the current file for entries matching `filter`
+    /// and returns at most `limit` of the newest, oldest first. Archives last
+    /// written before the filter's time range are skipped without reading.
+    pub fn search(&self, filter: &LogFilter, limit: usize) -> anyhow::Result<Vec<LogEntry>> {
+        let mut files = self.archives();
+        files.push(self.current_path());
+
+        let mut matches = VecDeque::new();
+        for path in files {
+            if filter
+                .after
+                .is_some_and(|after| modified(&path).is_some_and(|time| time < after))
+            {
+                continue;
+            }
+            let lines = match read_lines(&path) {
+                Ok(lines) => lines,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for line in lines {
+                let Ok(entry) = serde_json::from_str::<LogEntry>(&line) else {
+                    continue;
+                };
+                if filter.matches(&entry) {
+                    matches.push_back(entry);
+                    if matches.len() > limit {
+                        matches.pop_front();
+                    }
+                }
+            }
+        }
+        Ok(matches.into())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Lines of a log file, transparently decompressing `.gz` archives.
+fn read_lines(path: &Path) -> io::Result<impl Iterator<Item = String>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(BufReader::new(reader).lines().map_while(Result::ok))
+}
+
+/// Replaces `path` with a gzipped `<path>.gz`.
+fn compress(path: &Path) -> anyhow::Result<()> {
+    let mut gz_name = path.as_os_str().to_owned();
+    gz_name.push(".gz");
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(PathBuf::from(gz_name))?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logs::LogLevel;
+    use std::collections::BTreeMap;
+    use std::time::UNIX_EPOCH;
+
+    fn temp_store(name: &str, settings: LogSettings) -> LogStore {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("synapse-logs-{}-{}-{}", name, std::process::id(), nanos));
+        LogStore::with_dir(dir, settings)
+    }
+
+    fn settings(max_file_bytes: u64, compress: bool, retention_days: u64) -> LogSettings {
+        LogSettings {
+            max_file_bytes,
+            max_file_age_hours: 0,
+            compress,
+            retention_days,
+            ..LogSettings::default()
+        }
+    }
+
+    fn entry(level: LogLevel, message: &str, time: SystemTime) -> LogEntry {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+        LogEntry {
+            timestamp: since_epoch.as_secs(),
+            timestamp_ms: since_epoch.as_millis() as u64,
+            level,
+            source: "test".to_string(),
+            message: message.to_string(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    fn messages(entries: &[LogEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.message.as_str()).collect()
+    }
+
+    #[test]
+    fn rotates_once_the_size_limit_is_reached() {
+        let now = SystemTime::now();
+        let line_len = serde_json::to_string(&entry(LogLevel::Info, "entry 1", now)).unwrap().len() as u64 + 1;
+        let mut store = temp_store("size", settings(2 * line_len, false, 0));
+
+        store.append(&entry(LogLevel::Info, "entry 1", now)).unwrap();
+        store.append(&entry(LogLevel::Info, "entry 2", now)).unwrap();
+        assert!(store.archives().is_empty());
+
+        store.append(&entry(LogLevel::Info, "entry 3", now)).unwrap();
+        let archives = store.archives();
+        assert_eq!(archives.len(), 1);
+        assert_eq!(read_lines(&archives[0]).unwrap().count(), 2);
+        assert_eq!(read_lines(&store.current_path()).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn rotates_once_the_first_entry_is_too_old() {
+        let mut first = temp_store("age", LogSettings {
+            max_file_age_hours: 24,
+            ..settings(0, false, 0)
+        });
+        let now = SystemTime::now();
+        first.append(&entry(LogLevel::Info, "stale", now - Duration::from_secs(25 * 3600))).unwrap();
+
+        // A later session picks the first entry's time up from the file
+        let mut next = LogStore::with_dir(first.dir.clone(), first.settings.clone());
+        next.append(&entry(LogLevel::Info, "fresh", now)).unwrap();
+        assert_eq!(next.archives().len(), 1);
+        next.append(&entry(LogLevel::Info, "fresh again", now)).unwrap();
+        assert_eq!(next.archives().len(), 1);
+        assert_eq!(read_lines(&next.current_path()).unwrap().count(), 2);
+        let _ = fs::remove_dir_all(&next.dir);
+    }
+
+    #[test]
+    fn gzipped_segments_are_searched() {
+        let mut store = temp_store("gzip", settings(1, true, 0));
+        let now = SystemTime::now();
+        store.append(&entry(LogLevel::Error, "first failure", now)).unwrap();
+        store.append(&entry(LogLevel::Info, "progress", now)).unwrap();
+        store.append(&entry(LogLevel::Error, "second failure", now)).unwrap();
+
+        let archives = store.archives();
+        assert_eq!(archives.len(), 2);
+        assert!(archives.iter().all(|path| path.extension().is_some_and(|ext| ext == "gz")));
+        assert!(fs::read(&archives[0]).unwrap().starts_with(&[0x1f, 0x8b]));
+
+        let errors = LogFilter {
+            level: Some(LogLevel::Error),
+            ..LogFilter::default()
+        };
+        let found = store.search(&errors, 10).unwrap();
+        assert_eq!(messages(&found), vec!["first failure", "second failure"]);
+        // The limit keeps the newest matches
+        let found = store.search(&LogFilter::default(), 2).unwrap();
+        assert_eq!(messages(&found), vec!["progress", "second failure"]);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn prune_removes_archives_past_the_retention_period() {
+        let mut store = temp_store("prune", settings(0, true, 1));
+        let now = SystemTime::now();
+        store.append(&entry(LogLevel::Info, "old", now)).unwrap();
+        store.rotate().unwrap();
+        store.append(&entry(LogLevel::Info, "recent", now)).unwrap();
+        store.rotate().unwrap();
+        let archives = store.archives();
+        assert_eq!(archives.len(), 2);
+
+        let two_days_ago = now - Duration::from_secs(2 * 24 * 3600);
+        File::options()
+            .write(true)
+            .open(&archives[0])
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+        assert_eq!(store.prune().unwrap(), 1);
+        assert_eq!(store.archives(), vec![archives[1].clone()]);
+
+        // The current file is never pruned
+        let found = store.search(&LogFilter::default(), 10).unwrap();
+        assert_eq!(messages(&found), vec!["recent"]);
+        assert!(store.current_path().exists());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
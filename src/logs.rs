@@ -1,17 +1,137 @@
 // use gpui::*; // Commented out for CLI version
-use std::collections::VecDeque;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::log_export::{self, LogForwarder, LogFormatter};
+use crate::log_store::LogStore;
+use crate::settings::{LogFormat, LogSettings, LogTimezone};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+/// Well-known metadata keys.
+pub mod field {
+    pub const AGENT_ID: &str = "agent_id";
+    pub const REPORT_ID: &str = "report_id";
+    pub const TERMINAL_ID: &str = "terminal_id";
+    pub const SPAN_ID: &str = "span_id";
+    pub const TRACE_ID: &str = "trace_id";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
     pub timestamp: u64,
+    /// Milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub timestamp_ms: u64,
     pub level: LogLevel,
     pub source: String,
     pub message: String,
-    pub metadata: Option<String>,
+    /// Structured key/value fields; see `field` for the well-known keys.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl LogEntry {
+    pub fn time(&self) -> SystemTime {
+        // Entries written before millisecond timestamps only have `timestamp`
+        if self.timestamp_ms > 0 {
+            UNIX_EPOCH + Duration::from_millis(self.timestamp_ms)
+        } else {
+            UNIX_EPOCH + Duration::from_secs(self.timestamp)
+        }
+    }
+
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
 }
 
+/// Renders `time` in `timezone` as RFC 3339 with milliseconds when `format`
+/// is `rfc3339`, or with `format` as a chrono strftime pattern. Invalid
+/// patterns fall back to RFC 3339.
+pub fn format_time(time: SystemTime, format: &str, timezone: LogTimezone) -> String {
+    fn render<Tz: chrono::TimeZone>(time: DateTime<Tz>, format: &str, utc: bool) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        if !format.eq_ignore_ascii_case("rfc3339") {
+            let mut out = String::new();
+            if write!(out, "{}", time.format(format)).is_ok() {
+                return out;
+            }
+        }
+        time.to_rfc3339_opts(SecondsFormat::Millis, utc)
+    }
+
+    match timezone {
+        LogTimezone::Local => render(DateTime::<Local>::from(time), format, false),
+        LogTimezone::Utc => render(DateTime::<Utc>::from(time), format, true),
+    }
+}
+
+/// A log line an agent wrote as a JSON object.
 #[derive(Debug, Clone, PartialEq)]
+pub struct JsonLogLine {
+    pub level: Option<LogLevel>,
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Parses a JSON object log line. The message is taken from `message`, `msg`
+/// or `fields.message` (the whole line if none is present) and the level
+/// from `level` or `severity`; every other value becomes a field, nested
+/// objects flattened to dotted keys. Returns `None` for lines that aren't
+/// JSON objects.
+pub fn parse_json_line(line: &str) -> Option<JsonLogLine> {
+    let trimmed = line.trim();
+    if !trimmed.starts_with('{') {
+        return None;
+    }
+    let Value::Object(object) = serde_json::from_str(trimmed).ok()? else {
+        return None;
+    };
+
+    let mut fields = BTreeMap::new();
+    for (key, value) in object {
+        flatten_field(key, value, &mut fields);
+    }
+
+    let message = ["message", "msg", "fields.message"]
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .unwrap_or_else(|| trimmed.to_string());
+    let level = ["level", "severity"]
+        .iter()
+        .find_map(|key| fields.remove(*key))
+        .and_then(|level| LogLevel::parse(&level));
+
+    Some(JsonLogLine { level, message, fields })
+}
+
+fn flatten_field(key: String, value: Value, fields: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(object) => {
+            for (child, value) in object {
+                flatten_field(format!("{}.{}", key, child), value, fields);
+            }
+        }
+        Value::String(text) => {
+            fields.insert(key, text);
+        }
+        Value::Null => {}
+        other => {
+            fields.insert(key, other.to_string());
+        }
+    }
+}
+
+/// Ordered from least to most severe.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum LogLevel {
     Trace,
     Debug,
@@ -43,6 +163,55 @@ impl LogLevel {
             LogLevel::Critical => "CRITICAL",
         }
     }
+
+    /// Parses a level name case-insensitively, accepting common aliases.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "trace" => Some(LogLevel::Trace),
+            "debug" => Some(LogLevel::Debug),
+            "info" | "information" | "notice" => Some(LogLevel::Info),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "error" | "err" => Some(LogLevel::Error),
+            "critical" | "crit" | "fatal" | "panic" => Some(LogLevel::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// New log entries matching a filter, as they are added. Entries that arrive
+/// while the channel is full are dropped rather than blocking the logger.
+/// Dropping the subscription unsubscribes.
+pub struct LogSubscription {
+    pub receiver: Receiver<LogEntry>,
+    state: Arc<SubscriptionState>,
+}
+
+impl LogSubscription {
+    /// Number of entries dropped so far because the channel was full.
+    pub fn dropped(&self) -> usize {
+        self.state.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for LogSubscription {
+    fn drop(&mut self) {
+        self.state.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Shared between a subscription and the panel's end of it.
+#[derive(Default)]
+struct SubscriptionState {
+    dropped: AtomicUsize,
+    /// Set once the subscription is dropped, so the panel can forget it
+    /// without waiting for a matching entry to fail to send.
+    closed: AtomicBool,
+}
+
+struct LogSubscriber {
+    filter: LogFilter,
+    sender: SyncSender<LogEntry>,
+    state: Arc<SubscriptionState>,
 }
 
 pub struct LogPanel {
@@ -50,30 +219,140 @@ pub struct LogPanel {
     filter: LogFilter,
     max_logs: usize,
     auto_scroll: bool,
+    store: Option<LogStore>,
+    settings: LogSettings,
+    forwarder: Option<LogForwarder>,
+    subscribers: Vec<LogSubscriber>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LevelMatch {
+    #[default]
+    Exact,
+    /// The level and everything more severe.
+    AtLeast,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct LogFilter {
     pub level: Option<LogLevel>,
-    pub source: Option<String>,
+    pub level_match: LevelMatch,
+    /// Entries whose source contains any of these; empty matches all.
+    pub sources: Vec<String>,
+    /// Entries whose source contains any of these are hidden.
+    pub excluded_sources: Vec<String>,
     pub search_query: String,
+    /// Matched against the message.
+    pub regex: Option<Regex>,
+    /// Inclusive time range.
+    pub after: Option<SystemTime>,
+    pub before: Option<SystemTime>,
+    /// Metadata fields an entry must carry with exactly these values.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl LogFilter {
+    /// Sets the message regex, failing if `pattern` doesn't compile. An empty
+    /// pattern clears it.
+    pub fn set_regex(&mut self, pattern: &str) -> anyhow::Result<()> {
+        self.regex = if pattern.is_empty() {
+            None
+        } else {
+            Some(Regex::new(pattern)?)
+        };
+        Ok(())
+    }
+
+    pub fn matches(&self, log: &LogEntry) -> bool {
+        if let Some(ref level) = self.level {
+            let matched = match self.level_match {
+                LevelMatch::Exact => log.level == *level,
+                LevelMatch::AtLeast => log.level >= *level,
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if !self.sources.is_empty() && !self.sources.iter().any(|s| log.source.contains(s.as_str())) {
+            return false;
+        }
+        if self.excluded_sources.iter().any(|s| log.source.contains(s.as_str())) {
+            return false;
+        }
+        if let Some(ref regex) = self.regex {
+            if !regex.is_match(&log.message) {
+                return false;
+            }
+        }
+        let time = log.time();
+        if self.after.is_some_and(|after| time < after) || self.before.is_some_and(|before| time > before) {
+            return false;
+        }
+        if !self.search_query.is_empty() {
+            let query = self.search_query.to_lowercase();
+            if !log.message.to_lowercase().contains(&query)
+                && !log.source.to_lowercase().contains(&query)
+            {
+                return false;
+            }
+        }
+        self.fields
+            .iter()
+            .all(|(key, value)| log.field(key) == Some(value.as_str()))
+    }
 }
 
 impl LogPanel {
     pub fn new(_cx: &mut ()) -> Self {
         Self {
             logs: VecDeque::new(),
-            filter: LogFilter {
-                level: None,
-                source: None,
-                search_query: String::new(),
-            },
+            filter: LogFilter::default(),
             max_logs: 10000,
             auto_scroll: true,
+            store: None,
+            settings: LogSettings {
+                persist: false,
+                ..LogSettings::default()
+            },
+            forwarder: None,
+            subscribers: Vec::new(),
         }
     }
 
+    /// Also appends every entry to the rotating log files when `persist` is
+    /// set, and to the forwarding sink when forwarding is enabled.
+    pub fn with_settings(settings: &LogSettings) -> Self {
+        let mut panel = Self::new(&mut ());
+        panel.settings = settings.clone();
+        if settings.persist {
+            panel.store = Some(LogStore::new(settings.clone()));
+        }
+        if settings.forward.enabled {
+            match LogForwarder::new(settings) {
+                Ok(forwarder) => panel.forwarder = Some(forwarder),
+                Err(e) => panel.log(
+                    LogLevel::Error,
+                    "logs",
+                    format!("Log forwarding disabled, could not open the sink: {}", e),
+                ),
+            }
+        }
+        panel
+    }
+
     pub fn add_log(&mut self, entry: LogEntry) {
+        if let Some(ref mut store) = self.store {
+            // A full disk shouldn't take logging in memory down with it
+            let _ = store.append(&entry);
+        }
+        if let Some(ref mut forwarder) = self.forwarder {
+            // Not logged as an entry of our own, which would be forwarded
+            // to the failing sink again
+            if let Some(notice) = forwarder.forward_or_notice(&entry) {
+                eprintln!("{}", notice);
+            }
+        }
+        self.notify_subscribers(&entry);
         self.logs.push_back(entry);
         if self.logs.len() > self.max_logs {
             self.logs.pop_front();
@@ -81,60 +360,111 @@ impl LogPanel {
     }
 
     pub fn log(&mut self, level: LogLevel, source: &str, message: String) {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
+        self.log_with_fields(level, source, message, BTreeMap::new());
+    }
+
+    pub fn log_with_fields(
+        &mut self,
+        level: LogLevel,
+        source: &str,
+        message: String,
+        metadata: BTreeMap<String, String>,
+    ) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
         self.add_log(
             LogEntry {
-                timestamp,
+                timestamp: now.as_secs(),
+                timestamp_ms: now.as_millis() as u64,
                 level,
                 source: source.to_string(),
                 message,
-                metadata: None,
+                metadata,
             },
         );
     }
 
+    /// Follows new entries matching `filter`, buffering up to `capacity` of
+    /// them for the subscriber. A capacity of zero is raised to one, since an
+    /// unbuffered channel could never be sent to without blocking.
+    pub fn subscribe(&mut self, filter: LogFilter, capacity: usize) -> LogSubscription {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let state = Arc::new(SubscriptionState::default());
+        self.subscribers.push(LogSubscriber {
+            filter,
+            sender,
+            state: state.clone(),
+        });
+        LogSubscription { receiver, state }
+    }
+
+    fn notify_subscribers(&mut self, entry: &LogEntry) {
+        // Drop subscribers whose receiving end has gone away
+        self.subscribers.retain(|subscriber| {
+            if subscriber.state.closed.load(Ordering::Relaxed) {
+                return false;
+            }
+            if !subscriber.filter.matches(entry) {
+                return true;
+            }
+            match subscriber.sender.try_send(entry.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    subscriber.state.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
     pub fn clear(&mut self) {
         self.logs.clear();
     }
 
+    pub fn filter(&self) -> &LogFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: LogFilter) {
+        self.filter = filter;
+    }
+
     pub fn filtered_logs(&self) -> Vec<&LogEntry> {
         self.logs
             .iter()
-            .filter(|log| {
-                if let Some(ref level) = self.filter.level {
-                    if log.level != *level {
-                        return false;
-                    }
-                }
-                if let Some(ref source) = self.filter.source {
-                    if !log.source.contains(source) {
-                        return false;
-                    }
-                }
-                if !self.filter.search_query.is_empty() {
-                    let query = self.filter.search_query.to_lowercase();
-                    if !log.message.to_lowercase().contains(&query)
-                        && !log.source.to_lowercase().contains(&query)
-                    {
-                        return false;
-                    }
-                }
-                true
-            })
+            .filter(|log| self.filter.matches(log))
             .collect()
     }
 
-    pub fn format_timestamp(&self, timestamp: u64) -> String {
-        // Simple timestamp formatting
-        let datetime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(timestamp);
-        format!("{:?}", datetime)
-            .chars()
-            .take(19)
-            .collect()
+    /// Searches the log files on disk, including rotated archives, falling
+    /// back to the in-memory entries when logs aren't persisted. Returns at
+    /// most `limit` of the newest matches, oldest first.
+    pub fn search(&self, filter: &LogFilter, limit: usize) -> anyhow::Result<Vec<LogEntry>> {
+        match self.store {
+            Some(ref store) => store.search(filter, limit),
+            None => {
+                let matches: Vec<&LogEntry> = self.logs.iter().filter(|log| filter.matches(log)).collect();
+                let skip = matches.len().saturating_sub(limit);
+                Ok(matches.into_iter().skip(skip).cloned().collect())
+            }
+        }
+    }
+
+    /// Formats an entry's time with the configured format and timezone.
+    pub fn format_timestamp(&self, entry: &LogEntry) -> String {
+        format_time(entry.time(), &self.settings.timestamp_format, self.settings.timezone)
+    }
+
+    /// Renders the logs matching the current filter.
+    pub fn export(&self, format: LogFormat) -> String {
+        let formatter = LogFormatter::new(format, &self.settings);
+        log_export::export_logs(&self.filtered_logs(), &formatter)
+    }
+
+    pub fn export_to_file(&self, path: &std::path::Path, format: LogFormat) -> anyhow::Result<()> {
+        std::fs::write(path, self.export(format))?;
+        Ok(())
     }
 
     pub fn get_logs(&self) -> &VecDeque<LogEntry> {
@@ -143,3 +473,125 @@ impl LogPanel {
 }
 
 // UI rendering code commented out for CLI version
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{LogForwardSettings, LogSink};
+
+    #[test]
+    fn zero_capacity_subscription_still_receives_entries() {
+        let mut panel = LogPanel::new(&mut ());
+        let subscription = panel.subscribe(LogFilter::default(), 0);
+
+        panel.log(LogLevel::Info, "test", "first".to_string());
+        panel.log(LogLevel::Info, "test", "second".to_string());
+
+        let received: Vec<String> = subscription.receiver.try_iter().map(|e| e.message).collect();
+        assert_eq!(received, vec!["first".to_string()]);
+        assert_eq!(subscription.dropped(), 1);
+    }
+
+    #[test]
+    fn dropped_subscriptions_are_removed() {
+        let mut panel = LogPanel::new(&mut ());
+        let errors_only = LogFilter {
+            level: Some(LogLevel::Error),
+            ..LogFilter::default()
+        };
+        let subscription = panel.subscribe(errors_only, 8);
+        drop(subscription);
+
+        // Not even a matching entry is needed to notice
+        panel.log(LogLevel::Info, "test", "ignored".to_string());
+        assert!(panel.subscribers.is_empty());
+    }
+
+    /// A panel with entries of several levels from two agents, one of them
+    /// also tagged with a report.
+    fn panel_with_agent_logs() -> LogPanel {
+        let mut panel = LogPanel::new(&mut ());
+        let entries = [
+            (LogLevel::Info, "a1 info", Some("a1")),
+            (LogLevel::Warn, "a1 warn", Some("a1")),
+            (LogLevel::Warn, "untagged warn", None),
+            (LogLevel::Error, "a2 error", Some("a2")),
+            (LogLevel::Critical, "a1 critical", Some("a1")),
+        ];
+        for (level, message, agent) in entries {
+            let mut metadata = BTreeMap::new();
+            if let Some(agent) = agent {
+                metadata.insert(field::AGENT_ID.to_string(), agent.to_string());
+            }
+            if level == LogLevel::Critical {
+                metadata.insert(field::REPORT_ID.to_string(), "r1".to_string());
+            }
+            panel.log_with_fields(level, "agent", message.to_string(), metadata);
+        }
+        panel
+    }
+
+    fn filtered(
+        panel: &mut LogPanel,
+        level: LogLevel,
+        level_match: LevelMatch,
+        fields: &[(&str, &str)],
+    ) -> Vec<String> {
+        panel.set_filter(LogFilter {
+            level: Some(level),
+            level_match,
+            fields: fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..LogFilter::default()
+        });
+        panel.filtered_logs().into_iter().map(|e| e.message.clone()).collect()
+    }
+
+    #[test]
+    fn at_least_level_combines_with_metadata() {
+        let mut panel = panel_with_agent_logs();
+        let a1 = [(field::AGENT_ID, "a1")];
+
+        assert_eq!(filtered(&mut panel, LogLevel::Warn, LevelMatch::AtLeast, &a1), vec!["a1 warn", "a1 critical"]);
+        assert_eq!(filtered(&mut panel, LogLevel::Trace, LevelMatch::AtLeast, &a1), vec!["a1 info", "a1 warn", "a1 critical"]);
+        assert_eq!(filtered(&mut panel, LogLevel::Error, LevelMatch::AtLeast, &[(field::AGENT_ID, "a2")]), vec!["a2 error"]);
+        // Every field has to match
+        let a1_r1 = [(field::AGENT_ID, "a1"), (field::REPORT_ID, "r1")];
+        assert_eq!(filtered(&mut panel, LogLevel::Info, LevelMatch::AtLeast, &a1_r1), vec!["a1 critical"]);
+        assert!(filtered(&mut panel, LogLevel::Warn, LevelMatch::AtLeast, &[(field::AGENT_ID, "a3")]).is_empty());
+    }
+
+    #[test]
+    fn exact_level_combines_with_metadata() {
+        let mut panel = panel_with_agent_logs();
+        let a1 = [(field::AGENT_ID, "a1")];
+
+        // Entries without the field don't match even at the right level
+        assert_eq!(filtered(&mut panel, LogLevel::Warn, LevelMatch::Exact, &a1), vec!["a1 warn"]);
+        assert_eq!(filtered(&mut panel, LogLevel::Warn, LevelMatch::Exact, &[]), vec!["a1 warn", "untagged warn"]);
+        assert!(filtered(&mut panel, LogLevel::Error, LevelMatch::Exact, &a1).is_empty());
+        let a1_r1 = [(field::AGENT_ID, "a1"), (field::REPORT_ID, "r1")];
+        assert_eq!(filtered(&mut panel, LogLevel::Critical, LevelMatch::Exact, &a1_r1), vec!["a1 critical"]);
+        assert!(filtered(&mut panel, LogLevel::Info, LevelMatch::Exact, &a1_r1).is_empty());
+    }
+
+    #[test]
+    fn unusable_forwarding_sink_is_reported() {
+        let settings = LogSettings {
+            persist: false,
+            forward: LogForwardSettings {
+                enabled: true,
+                sink: LogSink::File {
+                    path: "/nonexistent/synapse/forward.log".to_string(),
+                },
+                format: LogFormat::Text,
+            },
+            ..LogSettings::default()
+        };
+        let panel = LogPanel::with_settings(&settings);
+
+        assert!(panel.forwarder.is_none());
+        let entry = panel.get_logs().back().expect("an error entry");
+        assert_eq!(entry.level, LogLevel::Error);
+        assert!(entry.message.starts_with("Log forwarding disabled"));
+    }
+}
@@ -2,14 +2,22 @@
 mod accessibility;
 mod agent;
 mod ai_cli;
+mod alerts;
 // mod app;
 mod changelog;
 mod code_editor;
 mod guides;
 // mod grid;
 // mod lens;
+mod log_export;
+mod log_store;
 mod logs;
+mod pricing;
 mod report;
+mod report_analytics;
+mod report_diff;
+mod report_export;
+mod report_store;
 // mod roster;
 mod scripting;
 mod settings;
@@ -17,6 +25,14 @@ mod settings;
 // mod status_bar;
 // mod terminal;
 mod themes;
+mod vt;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// How often the CLI polls supervised agents.
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(100);
 
 fn main() {
     println!("SYNAPSE - Runtime-First AI Orchestration Console");
@@ -34,8 +50,26 @@ fn main() {
     println!();
     
     // Initialize agent manager
-    let _agent_manager = agent::AgentManager::new();
-    println!("Agent Manager initialized");
+    let mut agent_manager = agent::AgentManager::new();
+    let mut report_window = report::ReportWindow::with_settings(&settings.reports);
+    report_window.set_pricing(&settings.pricing);
+    println!("Reports loaded: {}", report_window.get_reports().len());
+    agent_manager.set_report_window(std::sync::Arc::new(std::sync::Mutex::new(report_window)));
+    let mut log_panel = logs::LogPanel::with_settings(&settings.logs);
+    let mut alert_manager = alerts::AlertManager::new();
+    alert_manager.attach(&mut log_panel);
+    println!("Alert rules loaded: {}", alert_manager.get_rules().len());
+    agent_manager.set_log_panel(std::sync::Arc::new(std::sync::Mutex::new(log_panel)));
+    println!("Agent Manager initialized ({} agents)", agent_manager.get_agents().len());
+    agent_manager.apply_settings(&settings.agents);
+    match agent_manager.start_auto_agents(&settings.agents) {
+        Ok(failed) => {
+            for id in failed {
+                println!("  - failed to auto-start {}", id);
+            }
+        }
+        Err(e) => println!("  - auto-start skipped: {}", e),
+    }
     
     // Initialize AI CLI manager
     let mut ai_cli_manager = ai_cli::AICLIManager::new();
@@ -71,4 +105,83 @@ fn main() {
     println!("All core systems initialized successfully.");
     println!();
     println!("Note: Full UI with GPUI will be available once API issues are resolved.");
+
+    // Supervise auto-started agents until they finish or we are told to quit
+    let mut script_engine = scripting::ScriptEngine::new();
+    let accessibility = accessibility::AccessibilityManager::new();
+    let interrupted = install_signal_handlers();
+    if agent_manager.has_active_agents() {
+        println!();
+        println!("Supervising agents. Press Ctrl+C to stop them and exit.");
+    }
+    while agent_manager.has_active_agents() && !interrupted.load(Ordering::SeqCst) {
+        agent_manager.update_agent_status();
+        report_budget_alerts(&agent_manager);
+        run_alerts(&mut alert_manager, &mut agent_manager, &mut script_engine, &accessibility);
+        thread::sleep(SUPERVISE_INTERVAL);
+    }
+
+    // Agents run in their own process groups and would outlive us otherwise
+    agent_manager.shutdown();
+    report_budget_alerts(&agent_manager);
+    run_alerts(&mut alert_manager, &mut agent_manager, &mut script_engine, &accessibility);
+}
+
+/// Runs the alert rules over the entries logged since the last call and
+/// prints their status messages and failed actions.
+fn run_alerts(
+    alert_manager: &mut alerts::AlertManager,
+    agent_manager: &mut agent::AgentManager,
+    script_engine: &mut scripting::ScriptEngine,
+    accessibility: &accessibility::AccessibilityManager,
+) {
+    let firings = alert_manager.process(&mut alerts::AlertTargets {
+        agents: Some(agent_manager),
+        scripts: Some(script_engine),
+        accessibility: Some(accessibility),
+    });
+    for firing in firings {
+        for error in &firing.errors {
+            println!("Alert {} failed: {}", firing.rule_name, error);
+        }
+    }
+    for message in alert_manager.take_status_messages() {
+        println!("[{}] {}", message.level.as_str(), message.text);
+    }
+}
+
+/// Prints budget alerts raised by finished runs and logs them as warnings.
+fn report_budget_alerts(agent_manager: &agent::AgentManager) {
+    let alerts = agent_manager.report_window().lock().unwrap().take_budget_alerts();
+    for alert in alerts {
+        println!("Budget alert: {}", alert.message());
+        agent_manager
+            .log_panel()
+            .lock()
+            .unwrap()
+            .log(logs::LogLevel::Warn, "budget", alert.message());
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Sets the returned flag on SIGINT, SIGTERM or SIGHUP instead of exiting,
+/// so running agents can be shut down first.
+#[cfg(unix)]
+fn install_signal_handlers() -> &'static AtomicBool {
+    extern "C" fn handle(_signal: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(signal, handle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+    &INTERRUPTED
+}
+
+#[cfg(not(unix))]
+fn install_signal_handlers() -> &'static AtomicBool {
+    &INTERRUPTED
 }
\ No newline at end of file
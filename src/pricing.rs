@@ -0,0 +1,71 @@
+use crate::report::ReportMetrics;
+use crate::settings::{ModelPrice, PricingSettings};
+use chrono::NaiveDate;
+
+/// Picks the price entry for a tool/model pair. An entry naming the model
+/// wins over a tool-wide entry without one.
+fn find_price<'a>(pricing: &'a PricingSettings, tool: &str, model: Option<&str>) -> Option<&'a ModelPrice> {
+    let for_tool = pricing
+        .models
+        .iter()
+        .filter(|p| p.tool.eq_ignore_ascii_case(tool));
+
+    let mut fallback = None;
+    for price in for_tool {
+        match (&price.model, model) {
+            (Some(priced), Some(model)) if priced.eq_ignore_ascii_case(model) => return Some(price),
+            (None, _) => fallback = fallback.or(Some(price)),
+            _ => {}
+        }
+    }
+    fallback
+}
+
+/// Estimated cost of a run in the table's currency, or `None` if the tool has
+/// no price entry or no token usage is known. Token totals that aren't split
+/// into input and output are charged at the input rate.
+pub fn estimate_cost(
+    pricing: &PricingSettings,
+    tool: Option<&str>,
+    model: Option<&str>,
+    metrics: &ReportMetrics,
+) -> Option<f64> {
+    let price = find_price(pricing, tool?, model)?;
+
+    let (input, output) = match (metrics.input_tokens, metrics.output_tokens) {
+        (None, None) => (metrics.tokens_used?, 0),
+        (input, output) => (input.unwrap_or(0), output.unwrap_or(0)),
+    };
+    Some(
+        input as f64 / 1_000_000.0 * price.input_per_million
+            + output as f64 / 1_000_000.0 * price.output_per_million,
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetScope {
+    Run { report_id: String, agent_name: String },
+    Day(NaiveDate),
+}
+
+/// Raised the first time a run or a calendar day goes over its budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetAlert {
+    pub scope: BudgetScope,
+    pub spent: f64,
+    pub limit: f64,
+    pub currency: String,
+}
+
+impl BudgetAlert {
+    pub fn message(&self) -> String {
+        let scope = match &self.scope {
+            BudgetScope::Run { agent_name, .. } => format!("Run of {}", agent_name),
+            BudgetScope::Day(day) => format!("Spend on {}", day),
+        };
+        format!(
+            "{} cost {:.2} {}, over the budget of {:.2} {}",
+            scope, self.spent, self.currency, self.limit, self.currency
+        )
+    }
+}
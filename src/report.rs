@@ -1,7 +1,19 @@
 // use gpui::*; // Commented out for CLI version
+use crate::pricing::{self, BudgetAlert, BudgetScope};
+use crate::report_analytics::ReportAnalytics;
+use crate::report_diff::ReportDiff;
+use crate::report_export::{self, ExportFormat};
+use crate::report_store::ReportStore;
+use crate::settings::{PricingSettings, ReportSettings};
+use chrono::{DateTime, Local, NaiveDate};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the retention policy is re-applied while reports keep coming in.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Report {
@@ -13,6 +25,72 @@ pub struct Report {
     pub output: String,
     pub metrics: ReportMetrics,
     pub duration_ms: u64,
+    /// Wall-clock start and end in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub started_at_ms: u64,
+    #[serde(default)]
+    pub finished_at_ms: Option<u64>,
+    /// Monotonic start of the run, used for the duration. Not persisted.
+    #[serde(skip)]
+    pub started: Option<Instant>,
+    /// AI CLI tool id and model the run used, for pricing.
+    #[serde(default)]
+    pub tool: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl Report {
+    pub fn new(id: String, title: String, agent_name: String) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Self {
+            id,
+            title,
+            agent_name,
+            timestamp: now.as_secs(),
+            status: ReportStatus::Running,
+            output: String::new(),
+            metrics: ReportMetrics::default(),
+            duration_ms: 0,
+            started_at_ms: now.as_millis() as u64,
+            finished_at_ms: None,
+            started: Some(Instant::now()),
+            tool: None,
+            model: None,
+        }
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        // Reports written before millisecond timestamps only have `timestamp`
+        if self.started_at_ms > 0 {
+            UNIX_EPOCH + Duration::from_millis(self.started_at_ms)
+        } else {
+            UNIX_EPOCH + Duration::from_secs(self.timestamp)
+        }
+    }
+
+    pub fn finished_at(&self) -> Option<SystemTime> {
+        self.finished_at_ms.map(|ms| UNIX_EPOCH + Duration::from_millis(ms))
+    }
+
+    /// Marks the run as ended now and computes its duration.
+    fn finish(&mut self) {
+        let now = SystemTime::now();
+        self.finished_at_ms = Some(now.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64);
+        self.duration_ms = match self.started {
+            Some(started) => started.elapsed().as_millis() as u64,
+            // Loaded from disk; fall back to the wall clock
+            None => now
+                .duration_since(self.started_at())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        };
+    }
+}
+
+/// The local day a run's cost counts towards for the daily budget.
+fn budget_day(report: &Report) -> NaiveDate {
+    DateTime::<Local>::from(report.started_at()).date_naive()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -21,28 +99,167 @@ pub enum ReportStatus {
     Success,
     Failed,
     Cancelled,
+    /// Stopped by the watchdog after exceeding the run timeout.
+    TimedOut,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ReportStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ReportStatus::Running => "Running",
+            ReportStatus::Success => "Success",
+            ReportStatus::Failed => "Failed",
+            ReportStatus::Cancelled => "Cancelled",
+            ReportStatus::TimedOut => "TimedOut",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, ReportStatus::Running)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ReportMetrics {
     pub tokens_used: Option<u64>,
     pub api_calls: u64,
     pub errors: u64,
     pub warnings: u64,
+    #[serde(default)]
+    pub input_tokens: Option<u64>,
+    #[serde(default)]
+    pub output_tokens: Option<u64>,
+    /// Estimated from `PricingSettings`, in its currency. Falls back to the
+    /// cost reported by the tool itself when the price table has no entry.
+    #[serde(default)]
+    pub cost: Option<f64>,
 }
 
 pub struct ReportWindow {
     reports: VecDeque<Report>,
     selected_report: Option<usize>,
     filter: ReportFilter,
-    max_reports: usize,
+    settings: ReportSettings,
+    store: Option<ReportStore>,
+    /// How many of the stored reports are currently held in `reports`.
+    loaded_from_store: usize,
+    /// Finished reports held in memory that could not be written to the
+    /// store, so they don't count towards `loaded_from_store`.
+    unsaved: HashSet<String>,
+    /// When the retention policy was last applied.
+    last_retention: Option<Instant>,
+    pricing: PricingSettings,
+    budget_alerts: Vec<BudgetAlert>,
+    /// Runs and days already alerted on, so each crossing is reported once.
+    /// Entries go once the reports they cover are dropped from memory.
+    alerted: HashSet<String>,
 }
 
-#[derive(Debug, Clone)]
+/// Which reports to show and in what order. Empty and `None` fields don't
+/// filter; bounds are inclusive.
+#[derive(Debug, Clone, Default)]
 pub struct ReportFilter {
-    pub status: Option<ReportStatus>,
+    /// Reports with any of these statuses; empty matches every status.
+    pub statuses: Vec<ReportStatus>,
     pub agent_name: Option<String>,
+    /// Case-insensitive substring of the title or output.
     pub search_query: String,
+    /// Matched against the output.
+    pub output_regex: Option<Regex>,
+    /// Window the run must have started in.
+    pub started_after: Option<SystemTime>,
+    pub started_before: Option<SystemTime>,
+    pub min_duration_ms: Option<u64>,
+    pub max_duration_ms: Option<u64>,
+    pub min_tokens: Option<u64>,
+    pub max_tokens: Option<u64>,
+    pub sort: ReportSort,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReportSort {
+    #[default]
+    Newest,
+    Oldest,
+    Slowest,
+    /// By cost, then by tokens for runs without a cost estimate.
+    MostExpensive,
+}
+
+impl ReportFilter {
+    /// Sets the output regex, failing if `pattern` doesn't compile. An empty
+    /// pattern clears it.
+    pub fn set_output_pattern(&mut self, pattern: &str) -> anyhow::Result<()> {
+        self.output_regex = if pattern.is_empty() {
+            None
+        } else {
+            Some(Regex::new(pattern)?)
+        };
+        Ok(())
+    }
+
+    pub fn matches(&self, report: &Report) -> bool {
+        if !self.statuses.is_empty() && !self.statuses.contains(&report.status) {
+            return false;
+        }
+        if let Some(ref agent) = self.agent_name {
+            if !report.agent_name.contains(agent) {
+                return false;
+            }
+        }
+        if !self.search_query.is_empty() {
+            let query = self.search_query.to_lowercase();
+            if !report.title.to_lowercase().contains(&query)
+                && !report.output.to_lowercase().contains(&query)
+            {
+                return false;
+            }
+        }
+        if let Some(ref regex) = self.output_regex {
+            if !regex.is_match(&report.output) {
+                return false;
+            }
+        }
+
+        let started = report.started_at();
+        if self.started_after.is_some_and(|after| started < after)
+            || self.started_before.is_some_and(|before| started > before)
+        {
+            return false;
+        }
+        if self.min_duration_ms.is_some_and(|min| report.duration_ms < min)
+            || self.max_duration_ms.is_some_and(|max| report.duration_ms > max)
+        {
+            return false;
+        }
+
+        // Runs without token usage never pass a token bound
+        if self.min_tokens.is_some() || self.max_tokens.is_some() {
+            let Some(tokens) = report.metrics.tokens_used else {
+                return false;
+            };
+            if self.min_tokens.is_some_and(|min| tokens < min)
+                || self.max_tokens.is_some_and(|max| tokens > max)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn sort(&self, reports: &mut [&Report]) {
+        match self.sort {
+            ReportSort::Newest => reports.sort_by_key(|r| Reverse(r.started_at())),
+            ReportSort::Oldest => reports.sort_by_key(|r| r.started_at()),
+            ReportSort::Slowest => reports.sort_by_key(|r| Reverse(r.duration_ms)),
+            ReportSort::MostExpensive => reports.sort_by(|a, b| {
+                let cost = |r: &Report| r.metrics.cost.unwrap_or(0.0);
+                cost(b)
+                    .total_cmp(&cost(a))
+                    .then_with(|| b.metrics.tokens_used.cmp(&a.metrics.tokens_used))
+            }),
+        }
+    }
 }
 
 impl ReportWindow {
@@ -50,64 +267,264 @@ impl ReportWindow {
         Self {
             reports: VecDeque::new(),
             selected_report: None,
-            filter: ReportFilter {
-                status: None,
-                agent_name: None,
-                search_query: String::new(),
+            filter: ReportFilter::default(),
+            settings: ReportSettings {
+                persist: false,
+                ..ReportSettings::default()
             },
-            max_reports: 100,
+            store: None,
+            loaded_from_store: 0,
+            unsaved: HashSet::new(),
+            last_retention: None,
+            pricing: PricingSettings::default(),
+            budget_alerts: Vec::new(),
+            alerted: HashSet::new(),
+        }
+    }
+
+    pub fn set_pricing(&mut self, pricing: &PricingSettings) {
+        self.pricing = pricing.clone();
+    }
+
+    /// Returns and clears the budget alerts raised since the last call.
+    pub fn take_budget_alerts(&mut self) -> Vec<BudgetAlert> {
+        std::mem::take(&mut self.budget_alerts)
+    }
+
+    /// Recomputes the cost of a report and raises alerts for budgets it
+    /// pushed over the limit.
+    fn apply_pricing(&mut self, id: &str) {
+        let Some(report) = self.reports.iter_mut().find(|r| r.id == id) else {
+            return;
+        };
+        report.metrics.cost = pricing::estimate_cost(
+            &self.pricing,
+            report.tool.as_deref(),
+            report.model.as_deref(),
+            &report.metrics,
+        )
+        .or(report.metrics.cost);
+        let Some(cost) = report.metrics.cost else {
+            return;
+        };
+
+        if let Some(limit) = self.pricing.run_budget {
+            if cost > limit && self.alerted.insert(report.id.clone()) {
+                self.budget_alerts.push(BudgetAlert {
+                    scope: BudgetScope::Run {
+                        report_id: report.id.clone(),
+                        agent_name: report.agent_name.clone(),
+                    },
+                    spent: cost,
+                    limit,
+                    currency: self.pricing.currency.clone(),
+                });
+            }
+        }
+
+        if let Some(limit) = self.pricing.daily_budget {
+            let day = budget_day(report);
+            // Only covers reports held in memory, which always includes today's
+            // unless history was paged out by `max_reports`
+            let spent: f64 = self
+                .reports
+                .iter()
+                .filter(|r| budget_day(r) == day)
+                .filter_map(|r| r.metrics.cost)
+                .sum();
+            if spent > limit && self.alerted.insert(day.to_string()) {
+                self.budget_alerts.push(BudgetAlert {
+                    scope: BudgetScope::Day(day),
+                    spent,
+                    limit,
+                    currency: self.pricing.currency.clone(),
+                });
+            }
         }
     }
 
+    /// Opens the on-disk report history, applies the retention policy and
+    /// loads the first page of stored reports.
+    pub fn with_settings(settings: &ReportSettings) -> Self {
+        let mut window = Self::new(&mut ());
+        window.settings = settings.clone();
+        if settings.persist {
+            window.store = Some(ReportStore::new());
+            let _ = window.apply_retention();
+            window.load_more();
+        }
+        window
+    }
+
     pub fn add_report(&mut self, report: Report) {
         self.reports.push_front(report);
-        if self.reports.len() > self.max_reports {
-            self.reports.pop_back();
+        self.trim_to_max();
+    }
+
+    fn trim_to_max(&mut self) {
+        let max = self.settings.max_reports;
+        while max > 0 && self.reports.len() > max {
+            if let Some(report) = self.reports.pop_back() {
+                self.forget(&report);
+            }
+        }
+    }
+
+    /// Whether `report` is one of the stored reports counted by
+    /// `loaded_from_store`.
+    fn is_stored(&self, report: &Report) -> bool {
+        self.store.is_some() && report.status.is_terminal() && !self.unsaved.contains(&report.id)
+    }
+
+    /// Updates the bookkeeping for a report dropped from memory.
+    fn forget(&mut self, report: &Report) {
+        if self.is_stored(report) {
+            self.loaded_from_store = self.loaded_from_store.saturating_sub(1);
+        }
+        self.unsaved.remove(&report.id);
+        self.alerted.remove(&report.id);
+        let day = budget_day(report);
+        if !self.reports.iter().any(|r| budget_day(r) == day) {
+            self.alerted.remove(&day.to_string());
+        }
+    }
+
+    /// Loads the next page of older reports from disk. Returns how many
+    /// reports were added. Paged-in history is not subject to `max_reports`
+    /// until the next call to `apply_retention`.
+    pub fn load_more(&mut self) -> usize {
+        let Some(ref store) = self.store else {
+            return 0;
+        };
+
+        let page = store.load(self.loaded_from_store, self.settings.page_size);
+        self.loaded_from_store += page.len();
+        let mut added = 0;
+        for report in page {
+            if !self.reports.iter().any(|r| r.id == report.id) {
+                self.reports.push_back(report);
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Prunes the on-disk history and the in-memory list according to the
+    /// configured retention policy.
+    pub fn apply_retention(&mut self) -> anyhow::Result<()> {
+        self.last_retention = Some(Instant::now());
+        if let Some(ref store) = self.store {
+            store.prune(self.settings.retention_days, self.settings.max_reports)?;
+        }
+
+        if self.settings.retention_days > 0 {
+            let cutoff = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .saturating_sub(self.settings.retention_days * 24 * 60 * 60);
+            let (kept, expired): (VecDeque<Report>, VecDeque<Report>) = std::mem::take(&mut self.reports)
+                .into_iter()
+                .partition(|r| !r.status.is_terminal() || r.timestamp >= cutoff);
+            self.reports = kept;
+            for report in &expired {
+                self.forget(report);
+            }
         }
+        self.trim_to_max();
+        Ok(())
     }
 
     pub fn update_report(&mut self, id: &str, status: ReportStatus, output: String) {
         if let Some(report) = self.reports.iter_mut().find(|r| r.id == id) {
+            let was_running = !report.status.is_terminal();
             report.status = status.clone();
             report.output = output;
-            if status == ReportStatus::Success || status == ReportStatus::Failed {
-                // Calculate duration
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                if now > report.timestamp {
-                    report.duration_ms = (now - report.timestamp) * 1000;
+            if was_running && status.is_terminal() {
+                report.finish();
+                self.apply_pricing(id);
+                if let Some(ref store) = self.store {
+                    let Some(report) = self.reports.iter().find(|r| r.id == id) else {
+                        return;
+                    };
+                    // The report stays available in memory even if it can't be written
+                    match store.append(report) {
+                        Ok(()) => self.loaded_from_store += 1,
+                        Err(e) => {
+                            eprintln!("Could not store report {}: {}", id, e);
+                            self.unsaved.insert(id.to_string());
+                        }
+                    }
+                    if self
+                        .last_retention
+                        .is_none_or(|last| last.elapsed() >= RETENTION_INTERVAL)
+                    {
+                        let _ = self.apply_retention();
+                    }
                 }
             }
         }
     }
 
+    pub fn set_metrics(&mut self, id: &str, metrics: ReportMetrics) {
+        if let Some(report) = self.reports.iter_mut().find(|r| r.id == id) {
+            report.metrics = metrics;
+            self.apply_pricing(id);
+        }
+    }
+
+    pub fn filter(&self) -> &ReportFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: ReportFilter) {
+        self.filter = filter;
+        self.selected_report = None;
+    }
+
+    /// Reports matching the current filter, in its sort order.
     pub fn filtered_reports(&self) -> Vec<&Report> {
-        self.reports
+        let mut reports: Vec<&Report> = self
+            .reports
             .iter()
-            .filter(|report| {
-                if let Some(ref status) = self.filter.status {
-                    if report.status != *status {
-                        return false;
-                    }
-                }
-                if let Some(ref agent) = self.filter.agent_name {
-                    if !report.agent_name.contains(agent) {
-                        return false;
-                    }
-                }
-                if !self.filter.search_query.is_empty() {
-                    let query = self.filter.search_query.to_lowercase();
-                    if !report.title.to_lowercase().contains(&query)
-                        && !report.output.to_lowercase().contains(&query)
-                    {
-                        return false;
-                    }
-                }
-                true
-            })
-            .collect()
+            .filter(|report| self.filter.matches(report))
+            .collect();
+        self.filter.sort(&mut reports);
+        reports
+    }
+
+    /// Aggregates the reports matching the current filter.
+    pub fn analytics(&self) -> ReportAnalytics {
+        ReportAnalytics::from_reports(&self.filtered_reports())
+    }
+
+    /// Renders the reports matching the current filter.
+    pub fn export(&self, format: ExportFormat) -> String {
+        report_export::export_reports(&self.filtered_reports(), format)
+    }
+
+    pub fn export_to_file(&self, path: &std::path::Path, format: ExportFormat) -> anyhow::Result<()> {
+        std::fs::write(path, self.export(format))?;
+        Ok(())
+    }
+
+    /// Compares two loaded reports, `old_id` being the baseline.
+    pub fn diff(&self, old_id: &str, new_id: &str) -> Option<ReportDiff> {
+        let old = self.reports.iter().find(|r| r.id == old_id)?;
+        let new = self.reports.iter().find(|r| r.id == new_id)?;
+        Some(ReportDiff::between(old, new))
+    }
+
+    /// Compares a report with the run of the same agent that started just
+    /// before it.
+    pub fn diff_with_previous(&self, id: &str) -> Option<ReportDiff> {
+        let report = self.reports.iter().find(|r| r.id == id)?;
+        let previous = self
+            .reports
+            .iter()
+            .filter(|r| r.agent_name == report.agent_name && r.started_at() < report.started_at())
+            .max_by_key(|r| r.started_at())?;
+        Some(ReportDiff::between(previous, report))
     }
 
     pub fn get_reports(&self) -> &VecDeque<Report> {
@@ -116,3 +533,207 @@ impl ReportWindow {
 }
 
 // UI rendering code commented out for CLI version
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_with_budgets(run_budget: Option<f64>, daily_budget: Option<f64>) -> ReportWindow {
+        let mut window = ReportWindow::new(&mut ());
+        window.set_pricing(&PricingSettings {
+            run_budget,
+            daily_budget,
+            ..PricingSettings::default()
+        });
+        window
+    }
+
+    fn start_run(window: &mut ReportWindow, id: &str) {
+        window.add_report(Report::new(id.to_string(), "run".to_string(), "agent".to_string()));
+    }
+
+    fn costing(cost: f64) -> ReportMetrics {
+        ReportMetrics {
+            cost: Some(cost),
+            ..ReportMetrics::default()
+        }
+    }
+
+    /// Four finished runs started a minute apart, "a" first.
+    fn window_for_filtering() -> ReportWindow {
+        let mut window = ReportWindow::new(&mut ());
+        let runs = [
+            ("a", "error: build failed", 300, Some(0.5), Some(500)),
+            ("b", "all tests passed", 100, None, Some(2000)),
+            ("c", "warning: 2 lints", 900, Some(1.5), Some(100)),
+            ("d", "Error: timeout", 200, None, None),
+        ];
+        for (i, (id, output, duration_ms, cost, tokens)) in runs.into_iter().enumerate() {
+            let mut report = Report::new(id.to_string(), "run".to_string(), "agent".to_string());
+            report.status = ReportStatus::Success;
+            report.output = output.to_string();
+            report.started_at_ms = 1_700_000_000_000 + i as u64 * 60_000;
+            report.duration_ms = duration_ms;
+            report.metrics.cost = cost;
+            report.metrics.tokens_used = tokens;
+            window.add_report(report);
+        }
+        window
+    }
+
+    fn filtered_ids(window: &ReportWindow) -> Vec<&str> {
+        window.filtered_reports().into_iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn output_regex_filters_reports() {
+        let mut window = window_for_filtering();
+        let mut filter = ReportFilter::default();
+        filter.set_output_pattern(r"^[Ee]rror:").unwrap();
+        window.set_filter(filter.clone());
+        assert_eq!(filtered_ids(&window), vec!["d", "a"]);
+
+        // The regex is case-sensitive, unlike the search query
+        filter.set_output_pattern(r"^error:").unwrap();
+        window.set_filter(filter.clone());
+        assert_eq!(filtered_ids(&window), vec!["a"]);
+
+        assert!(filter.set_output_pattern("(unclosed").is_err());
+        filter.set_output_pattern("").unwrap();
+        assert!(filter.output_regex.is_none());
+    }
+
+    #[test]
+    fn time_window_bounds_are_inclusive() {
+        let mut window = window_for_filtering();
+        let minute = |i: u64| UNIX_EPOCH + Duration::from_millis(1_700_000_000_000 + i * 60_000);
+        window.set_filter(ReportFilter {
+            started_after: Some(minute(1)),
+            started_before: Some(minute(2)),
+            ..ReportFilter::default()
+        });
+        assert_eq!(filtered_ids(&window), vec!["c", "b"]);
+
+        window.set_filter(ReportFilter {
+            started_after: Some(minute(2) + Duration::from_millis(1)),
+            ..ReportFilter::default()
+        });
+        assert_eq!(filtered_ids(&window), vec!["d"]);
+
+        window.set_filter(ReportFilter {
+            started_before: Some(minute(0) - Duration::from_millis(1)),
+            ..ReportFilter::default()
+        });
+        assert!(filtered_ids(&window).is_empty());
+    }
+
+    #[test]
+    fn filtered_reports_follow_the_sort_order() {
+        let mut window = window_for_filtering();
+        let mut sorted = |sort| {
+            window.set_filter(ReportFilter {
+                sort,
+                ..ReportFilter::default()
+            });
+            filtered_ids(&window).join("")
+        };
+        assert_eq!(sorted(ReportSort::Newest), "dcba");
+        assert_eq!(sorted(ReportSort::Oldest), "abcd");
+        assert_eq!(sorted(ReportSort::Slowest), "cadb");
+        // Runs without a cost fall back to their tokens, and no tokens last
+        assert_eq!(sorted(ReportSort::MostExpensive), "cabd");
+    }
+
+    #[test]
+    fn crossing_the_run_budget_alerts_once() {
+        let mut window = window_with_budgets(Some(1.0), None);
+        start_run(&mut window, "r1");
+
+        window.set_metrics("r1", costing(0.5));
+        assert!(window.take_budget_alerts().is_empty());
+
+        window.set_metrics("r1", costing(1.5));
+        window.set_metrics("r1", costing(2.0));
+        window.update_report("r1", ReportStatus::Success, String::new());
+        let alerts = window.take_budget_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].scope,
+            BudgetScope::Run {
+                report_id: "r1".to_string(),
+                agent_name: "agent".to_string(),
+            }
+        );
+        assert_eq!(alerts[0].spent, 1.5);
+        assert!(window.take_budget_alerts().is_empty());
+    }
+
+    #[test]
+    fn crossing_the_daily_budget_alerts_once() {
+        let mut window = window_with_budgets(None, Some(1.0));
+        start_run(&mut window, "r1");
+        start_run(&mut window, "r2");
+        start_run(&mut window, "r3");
+
+        window.set_metrics("r1", costing(0.6));
+        assert!(window.take_budget_alerts().is_empty());
+        window.set_metrics("r2", costing(0.6));
+        window.set_metrics("r3", costing(0.6));
+
+        let alerts = window.take_budget_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert!(matches!(alerts[0].scope, BudgetScope::Day(_)));
+        assert!((alerts[0].spent - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn alerts_are_forgotten_with_their_reports() {
+        let mut window = window_with_budgets(Some(1.0), Some(1.0));
+        window.settings.max_reports = 2;
+        let mut old = Report::new("old".to_string(), "run".to_string(), "agent".to_string());
+        old.started_at_ms -= 2 * 24 * 60 * 60 * 1000;
+        window.add_report(old);
+        window.set_metrics("old", costing(2.0));
+        start_run(&mut window, "today");
+        window.set_metrics("today", costing(2.0));
+        assert_eq!(window.take_budget_alerts().len(), 4);
+        assert_eq!(window.alerted.len(), 4);
+
+        // Dropping the old report leaves only today's alerts; a report from
+        // the same day keeps the day's alert from firing again
+        start_run(&mut window, "later");
+        window.set_metrics("later", costing(0.5));
+        assert!(window.take_budget_alerts().is_empty());
+        let today = budget_day(&window.reports[0]).to_string();
+        assert_eq!(window.alerted, HashSet::from(["today".to_string(), today]));
+    }
+
+    #[test]
+    fn report_that_could_not_be_stored_does_not_shift_paging() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir = std::env::temp_dir().join(format!("synapse-window-{}-{}", std::process::id(), nanos));
+        let mut window = ReportWindow::new(&mut ());
+        window.settings.max_reports = 2;
+        window.store = Some(ReportStore::with_dir(dir.clone()));
+
+        // A directory where today's day file should be makes the append fail
+        let day_file = dir.join(format!("{}.jsonl", chrono::Utc::now().format("%Y-%m-%d")));
+        std::fs::create_dir_all(&day_file).unwrap();
+        start_run(&mut window, "unsaved");
+        window.update_report("unsaved", ReportStatus::Failed, String::new());
+        assert_eq!(window.loaded_from_store, 0);
+
+        std::fs::remove_dir(&day_file).unwrap();
+        start_run(&mut window, "saved");
+        window.update_report("saved", ReportStatus::Success, String::new());
+        assert_eq!(window.loaded_from_store, 1);
+
+        // Making room for a new run drops the unsaved report, which was
+        // never counted as stored
+        start_run(&mut window, "running");
+        assert_eq!(window.get_reports().len(), 2);
+        assert_eq!(window.loaded_from_store, 1);
+        assert_eq!(window.load_more(), 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
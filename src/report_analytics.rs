@@ -0,0 +1,241 @@
+use crate::report::{Report, ReportStatus};
+use chrono::{DateTime, Local, NaiveDate};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Totals over a group of runs. Durations and success rate only consider
+/// finished runs; timed-out runs count as failures and cancelled runs towards
+/// neither success nor failure.
+#[derive(Debug, Clone, Default)]
+pub struct RunStats {
+    pub runs: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub timed_out: usize,
+    pub cancelled: usize,
+    pub running: usize,
+    /// `succeeded / (succeeded + failed + timed_out)`, or `None` if nothing has finished.
+    pub success_rate: Option<f64>,
+    pub total_duration_ms: u64,
+    pub p50_duration_ms: u64,
+    pub p95_duration_ms: u64,
+    pub tokens_used: u64,
+    /// Sum of the estimated costs of runs that have one.
+    pub cost: f64,
+    pub api_calls: u64,
+    pub errors: u64,
+    pub warnings: u64,
+}
+
+impl RunStats {
+    fn from_reports(reports: &[&Report]) -> Self {
+        let mut stats = RunStats {
+            runs: reports.len(),
+            ..RunStats::default()
+        };
+        let mut durations = Vec::new();
+
+        for report in reports {
+            match report.status {
+                ReportStatus::Success => stats.succeeded += 1,
+                ReportStatus::Failed => stats.failed += 1,
+                ReportStatus::TimedOut => stats.timed_out += 1,
+                ReportStatus::Cancelled => stats.cancelled += 1,
+                ReportStatus::Running => stats.running += 1,
+            }
+            if report.status.is_terminal() {
+                durations.push(report.duration_ms);
+            }
+            stats.tokens_used += report.metrics.tokens_used.unwrap_or(0);
+            stats.cost += report.metrics.cost.unwrap_or(0.0);
+            stats.api_calls += report.metrics.api_calls;
+            stats.errors += report.metrics.errors;
+            stats.warnings += report.metrics.warnings;
+        }
+
+        let decided = stats.succeeded + stats.failed + stats.timed_out;
+        if decided > 0 {
+            stats.success_rate = Some(stats.succeeded as f64 / decided as f64);
+        }
+
+        durations.sort_unstable();
+        stats.total_duration_ms = durations.iter().sum();
+        stats.p50_duration_ms = percentile(&durations, 50);
+        stats.p95_duration_ms = percentile(&durations, 95);
+        stats
+    }
+
+    /// Average tokens per run.
+    pub fn token_burn(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.tokens_used as f64 / self.runs as f64
+        }
+    }
+}
+
+/// Nearest-rank percentile of an ascending slice.
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReportAnalytics {
+    pub overall: RunStats,
+    pub per_agent: BTreeMap<String, RunStats>,
+    /// Keyed by the local calendar day the run started on.
+    pub per_day: BTreeMap<NaiveDate, RunStats>,
+}
+
+impl ReportAnalytics {
+    pub fn from_reports(reports: &[&Report]) -> Self {
+        let mut by_agent: BTreeMap<String, Vec<&Report>> = BTreeMap::new();
+        let mut by_day: BTreeMap<NaiveDate, Vec<&Report>> = BTreeMap::new();
+        for report in reports {
+            by_agent.entry(report.agent_name.clone()).or_default().push(report);
+            let day = DateTime::<Local>::from(report.started_at()).date_naive();
+            by_day.entry(day).or_default().push(report);
+        }
+
+        Self {
+            overall: RunStats::from_reports(reports),
+            per_agent: by_agent
+                .into_iter()
+                .map(|(agent, runs)| (agent, RunStats::from_reports(&runs)))
+                .collect(),
+            per_day: by_day
+                .into_iter()
+                .map(|(day, runs)| (day, RunStats::from_reports(&runs)))
+                .collect(),
+        }
+    }
+
+    /// Agents ordered by total tokens used, most expensive first.
+    pub fn agents_by_tokens(&self) -> Vec<(&str, &RunStats)> {
+        let mut agents: Vec<(&str, &RunStats)> =
+            self.per_agent.iter().map(|(name, stats)| (name.as_str(), stats)).collect();
+        agents.sort_by_key(|(_, stats)| Reverse(stats.tokens_used));
+        agents
+    }
+
+    /// Agents with at least one finished run, least reliable first.
+    pub fn agents_by_success_rate(&self) -> Vec<(&str, &RunStats)> {
+        let mut agents: Vec<(&str, &RunStats)> = self
+            .per_agent
+            .iter()
+            .filter(|(_, stats)| stats.success_rate.is_some())
+            .map(|(name, stats)| (name.as_str(), stats))
+            .collect();
+        agents.sort_by(|a, b| a.1.success_rate.partial_cmp(&b.1.success_rate).unwrap());
+        agents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noon UTC on 2025-01-30, the same calendar day in every common timezone.
+    const DAY_ONE_MS: u64 = 1_738_238_400_000;
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+    fn run(agent: &str, status: ReportStatus, duration_ms: u64, tokens: u64, day: u64) -> Report {
+        let mut report = Report::new(format!("{}-{}", agent, duration_ms), "run".to_string(), agent.to_string());
+        report.status = status;
+        report.duration_ms = duration_ms;
+        report.metrics.tokens_used = Some(tokens);
+        report.metrics.api_calls = 1;
+        report.started_at_ms = DAY_ONE_MS + day * DAY_MS;
+        report
+    }
+
+    fn fixture() -> Vec<Report> {
+        vec![
+            run("build", ReportStatus::Success, 100, 1000, 0),
+            run("build", ReportStatus::Failed, 300, 500, 0),
+            run("build", ReportStatus::TimedOut, 1000, 0, 1),
+            run("test", ReportStatus::Success, 200, 100, 1),
+            run("test", ReportStatus::Cancelled, 50, 100, 1),
+            run("test", ReportStatus::Running, 0, 300, 1),
+        ]
+    }
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let durations: Vec<u64> = (1..=20).map(|i| i * 10).collect();
+        assert_eq!(percentile(&durations, 50), 100);
+        assert_eq!(percentile(&durations, 95), 190);
+        assert_eq!(percentile(&durations, 100), 200);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[], 95), 0);
+    }
+
+    #[test]
+    fn overall_stats_cover_every_run() {
+        let reports = fixture();
+        let refs: Vec<&Report> = reports.iter().collect();
+        let stats = ReportAnalytics::from_reports(&refs).overall;
+
+        assert_eq!(stats.runs, 6);
+        assert_eq!(
+            (stats.succeeded, stats.failed, stats.timed_out, stats.cancelled, stats.running),
+            (2, 1, 1, 1, 1)
+        );
+        assert_eq!(stats.success_rate, Some(0.5));
+        // The running report has no duration yet
+        assert_eq!(stats.total_duration_ms, 1650);
+        assert_eq!(stats.p50_duration_ms, 200);
+        assert_eq!(stats.p95_duration_ms, 1000);
+        assert_eq!(stats.tokens_used, 2000);
+        assert_eq!(stats.api_calls, 6);
+        assert!((stats.token_burn() - 2000.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stats_are_grouped_per_agent_and_day() {
+        let reports = fixture();
+        let refs: Vec<&Report> = reports.iter().collect();
+        let analytics = ReportAnalytics::from_reports(&refs);
+
+        let build = &analytics.per_agent["build"];
+        assert_eq!((build.runs, build.tokens_used, build.p50_duration_ms), (3, 1500, 300));
+        assert_eq!(build.success_rate, Some(1.0 / 3.0));
+        let test = &analytics.per_agent["test"];
+        assert_eq!((test.runs, test.tokens_used, test.success_rate), (3, 500, Some(1.0)));
+
+        let day = |offset: u64| DateTime::<Local>::from(reports[0].started_at()).date_naive() + chrono::Days::new(offset);
+        assert_eq!(analytics.per_day.len(), 2);
+        assert_eq!(analytics.per_day[&day(0)].runs, 2);
+        assert_eq!(analytics.per_day[&day(1)].runs, 4);
+
+        let by_tokens: Vec<&str> = analytics.agents_by_tokens().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(by_tokens, vec!["build", "test"]);
+        let by_success: Vec<&str> = analytics.agents_by_success_rate().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(by_success, vec!["build", "test"]);
+    }
+
+    #[test]
+    fn no_reports_give_empty_stats() {
+        let analytics = ReportAnalytics::from_reports(&[]);
+        let stats = &analytics.overall;
+
+        assert_eq!(stats.runs, 0);
+        assert_eq!(stats.success_rate, None);
+        assert_eq!((stats.p50_duration_ms, stats.p95_duration_ms), (0, 0));
+        assert_eq!(stats.token_burn(), 0.0);
+        assert!(analytics.per_agent.is_empty() && analytics.per_day.is_empty());
+        assert!(analytics.agents_by_success_rate().is_empty());
+
+        // Runs that haven't finished don't make up a success rate either
+        let running = run("build", ReportStatus::Running, 0, 10, 0);
+        let stats = ReportAnalytics::from_reports(&[&running]).overall;
+        assert_eq!(stats.success_rate, None);
+        assert_eq!(stats.p95_duration_ms, 0);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::report::{Report, ReportStatus};
+use chrono::{DateTime, Local};
+use std::fmt::Display;
+
+/// Lines of unchanged output shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// Largest LCS table computed for a diff. Beyond this the differing middle
+/// of the outputs is shown as one block replacement instead.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+/// A run of changes with its surrounding context. Line numbers are 1-based
+/// as in `diff -u`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// A value in the old and the new run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    pub fn changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsDelta {
+    pub duration_ms: Change<u64>,
+    pub tokens_used: Change<Option<u64>>,
+    pub input_tokens: Change<Option<u64>>,
+    pub output_tokens: Change<Option<u64>>,
+    pub cost: Change<Option<f64>>,
+    pub api_calls: Change<u64>,
+    pub errors: Change<u64>,
+    pub warnings: Change<u64>,
+}
+
+/// Differences between two runs, `old` usually being the earlier one.
+#[derive(Debug, Clone)]
+pub struct ReportDiff {
+    pub old_id: String,
+    pub new_id: String,
+    pub old_label: String,
+    pub new_label: String,
+    pub status: Change<ReportStatus>,
+    pub metrics: MetricsDelta,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl ReportDiff {
+    pub fn between(old: &Report, new: &Report) -> Self {
+        let label = |report: &Report| {
+            format!(
+                "{} {}",
+                report.agent_name,
+                DateTime::<Local>::from(report.started_at()).format("%Y-%m-%d %H:%M:%S%.3f")
+            )
+        };
+        let change = |f: fn(&Report) -> u64| Change { old: f(old), new: f(new) };
+        let optional = |f: fn(&Report) -> Option<u64>| Change { old: f(old), new: f(new) };
+
+        Self {
+            old_id: old.id.clone(),
+            new_id: new.id.clone(),
+            old_label: label(old),
+            new_label: label(new),
+            status: Change {
+                old: old.status.clone(),
+                new: new.status.clone(),
+            },
+            metrics: MetricsDelta {
+                duration_ms: change(|r| r.duration_ms),
+                tokens_used: optional(|r| r.metrics.tokens_used),
+                input_tokens: optional(|r| r.metrics.input_tokens),
+                output_tokens: optional(|r| r.metrics.output_tokens),
+                cost: Change {
+                    old: old.metrics.cost,
+                    new: new.metrics.cost,
+                },
+                api_calls: change(|r| r.metrics.api_calls),
+                errors: change(|r| r.metrics.errors),
+                warnings: change(|r| r.metrics.warnings),
+            },
+            hunks: diff_lines(&old.output, &new.output),
+        }
+    }
+
+    pub fn output_changed(&self) -> bool {
+        !self.hunks.is_empty()
+    }
+
+    /// Renders a summary of the metric changes followed by the unified diff
+    /// of the outputs.
+    pub fn render(&self) -> String {
+        let mut out = format!("Comparing {} -> {}\n", self.old_label, self.new_label);
+        out.push_str(&format!(
+            "Status: {} -> {}\n",
+            self.status.old.as_str(),
+            self.status.new.as_str()
+        ));
+
+        let m = &self.metrics;
+        out.push_str(&count_row("Duration (ms)", &m.duration_ms));
+        out.push_str(&optional_row("Tokens", &m.tokens_used));
+        out.push_str(&optional_row("Input tokens", &m.input_tokens));
+        out.push_str(&optional_row("Output tokens", &m.output_tokens));
+        out.push_str(&cost_row(&m.cost));
+        out.push_str(&count_row("API calls", &m.api_calls));
+        out.push_str(&count_row("Errors", &m.errors));
+        out.push_str(&count_row("Warnings", &m.warnings));
+
+        if !self.output_changed() {
+            out.push_str("\nOutput is identical\n");
+            return out;
+        }
+        out.push_str(&format!("\n--- {}\n+++ {}\n", self.old_label, self.new_label));
+        for hunk in &self.hunks {
+            out.push_str(&format!(
+                "@@ -{} +{} @@\n",
+                hunk_range(hunk.old_start, hunk.old_len),
+                hunk_range(hunk.new_start, hunk.new_len)
+            ));
+            for line in &hunk.lines {
+                let (prefix, text) = match line {
+                    DiffLine::Context(text) => (' ', text),
+                    DiffLine::Removed(text) => ('-', text),
+                    DiffLine::Added(text) => ('+', text),
+                };
+                out.push(prefix);
+                out.push_str(text);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+fn signed<T: Display + PartialOrd + Default>(delta: T) -> String {
+    if delta > T::default() {
+        format!("+{}", delta)
+    } else {
+        delta.to_string()
+    }
+}
+
+fn percent(old: f64, new: f64) -> String {
+    if old == 0.0 {
+        String::new()
+    } else {
+        format!(", {}%", signed(((new - old) / old * 1000.0).round() / 10.0))
+    }
+}
+
+fn count_row(name: &str, change: &Change<u64>) -> String {
+    let delta = change.new as i64 - change.old as i64;
+    format!(
+        "{}: {} -> {} ({}{})\n",
+        name,
+        change.old,
+        change.new,
+        signed(delta),
+        percent(change.old as f64, change.new as f64)
+    )
+}
+
+fn optional_row(name: &str, change: &Change<Option<u64>>) -> String {
+    match (change.old, change.new) {
+        (Some(old), Some(new)) => count_row(name, &Change { old, new }),
+        (None, None) => String::new(),
+        (old, new) => {
+            let show = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_else(|| "n/a".to_string());
+            format!("{}: {} -> {}\n", name, show(old), show(new))
+        }
+    }
+}
+
+fn cost_row(change: &Change<Option<f64>>) -> String {
+    match (change.old, change.new) {
+        (Some(old), Some(new)) => format!(
+            "Cost: {:.4} -> {:.4} ({}{:.4}{})\n",
+            old,
+            new,
+            if new > old { "+" } else { "" },
+            new - old,
+            percent(old, new)
+        ),
+        (None, None) => String::new(),
+        (old, new) => {
+            let show = |v: Option<f64>| v.map(|v| format!("{:.4}", v)).unwrap_or_else(|| "n/a".to_string());
+            format!("Cost: {} -> {}\n", show(old), show(new))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Line-level edit script turning `old` into `new`, from an LCS over the
+/// lines between the common prefix and suffix.
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut script = vec![Edit::Equal; prefix];
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if (n + 1) * (m + 1) > MAX_LCS_CELLS {
+        script.extend(std::iter::repeat_n(Edit::Delete, n));
+        script.extend(std::iter::repeat_n(Edit::Insert, m));
+    } else {
+        // lcs[i][j] is the LCS length of old_mid[i..] and new_mid[j..]
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        let at = |i: usize, j: usize| i * (m + 1) + j;
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[at(i, j)] = if old_mid[i] == new_mid[j] {
+                    lcs[at(i + 1, j + 1)] + 1
+                } else {
+                    lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                script.push(Edit::Equal);
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[at(i + 1, j)] >= lcs[at(i, j + 1)]) {
+                script.push(Edit::Delete);
+                i += 1;
+            } else {
+                script.push(Edit::Insert);
+                j += 1;
+            }
+        }
+    }
+    script.extend(std::iter::repeat_n(Edit::Equal, suffix));
+    script
+}
+
+/// Unified diff hunks between two outputs, with `CONTEXT_LINES` of context.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffHunk> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let script = edit_script(&old, &new);
+
+    // Line positions in old and new before each step of the script
+    let mut positions = Vec::with_capacity(script.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in &script {
+        positions.push((i, j));
+        match edit {
+            Edit::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    // Group changed steps that are close enough to share context
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (step, edit) in script.iter().enumerate() {
+        if *edit == Edit::Equal {
+            continue;
+        }
+        let start = step.saturating_sub(CONTEXT_LINES);
+        let end = (step + 1 + CONTEXT_LINES).min(script.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let (old_from, new_from) = positions[start];
+            let (old_to, new_to) = positions[end];
+            let lines = script[start..end]
+                .iter()
+                .zip(&positions[start..end])
+                .map(|(edit, &(i, j))| match edit {
+                    Edit::Equal => DiffLine::Context(old[i].to_string()),
+                    Edit::Delete => DiffLine::Removed(old[i].to_string()),
+                    Edit::Insert => DiffLine::Added(new[j].to_string()),
+                })
+                .collect();
+            let old_len = old_to - old_from;
+            let new_len = new_to - new_from;
+            DiffHunk {
+                // An empty side points at the line before the hunk
+                old_start: if old_len == 0 { old_from } else { old_from + 1 },
+                old_len,
+                new_start: if new_len == 0 { new_from } else { new_from + 1 },
+                new_len,
+                lines,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(id: &str, output: &str) -> Report {
+        let mut report = Report::new(id.to_string(), "run".to_string(), "agent".to_string());
+        report.status = ReportStatus::Success;
+        report.output = output.to_string();
+        report.duration_ms = 1000;
+        report.metrics.tokens_used = Some(100);
+        report.metrics.cost = Some(0.01);
+        report.metrics.api_calls = 4;
+        report
+    }
+
+    fn numbered(lines: std::ops::RangeInclusive<usize>) -> String {
+        lines.map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn changed_output_and_metrics_are_reported() {
+        let old = report("old", &numbered(1..=10));
+        let mut new = report("new", &numbered(1..=10).replace("line 5\n", "line five\n"));
+        new.status = ReportStatus::Failed;
+        new.duration_ms = 1500;
+        new.metrics.tokens_used = Some(150);
+        new.metrics.cost = Some(0.02);
+        new.metrics.errors = 2;
+
+        let diff = ReportDiff::between(&old, &new);
+        assert!(diff.status.changed());
+        assert!(diff.metrics.duration_ms.changed() && diff.metrics.errors.changed());
+        assert!(!diff.metrics.api_calls.changed());
+        assert_eq!(
+            diff.hunks,
+            vec![DiffHunk {
+                old_start: 2,
+                old_len: 7,
+                new_start: 2,
+                new_len: 7,
+                lines: vec![
+                    DiffLine::Context("line 2".to_string()),
+                    DiffLine::Context("line 3".to_string()),
+                    DiffLine::Context("line 4".to_string()),
+                    DiffLine::Removed("line 5".to_string()),
+                    DiffLine::Added("line five".to_string()),
+                    DiffLine::Context("line 6".to_string()),
+                    DiffLine::Context("line 7".to_string()),
+                    DiffLine::Context("line 8".to_string()),
+                ],
+            }]
+        );
+
+        let rendered = diff.render();
+        assert!(rendered.contains("Status: Success -> Failed\n"));
+        assert!(rendered.contains("Duration (ms): 1000 -> 1500 (+500, +50%)\n"));
+        assert!(rendered.contains("Tokens: 100 -> 150 (+50, +50%)\n"));
+        assert!(rendered.contains("Cost: 0.0100 -> 0.0200 (+0.0100, +100%)\n"));
+        assert!(rendered.contains("Errors: 0 -> 2 (+2)\n"));
+        assert!(!rendered.contains("Input tokens"));
+        assert!(rendered.contains("@@ -2,7 +2,7 @@\n line 2\n line 3\n line 4\n-line 5\n+line five\n line 6\n"));
+    }
+
+    #[test]
+    fn distant_changes_get_their_own_hunks() {
+        let old = numbered(1..=20);
+        let new = format!("line 0\n{}line 21\n", numbered(2..=20));
+        let hunks = diff_lines(&old, &new);
+
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old_start, hunks[0].old_len, hunks[0].new_start, hunks[0].new_len), (1, 4, 1, 4));
+        assert_eq!((hunks[1].old_start, hunks[1].old_len, hunks[1].new_start, hunks[1].new_len), (18, 3, 18, 4));
+        assert_eq!(hunks[1].lines.last(), Some(&DiffLine::Added("line 21".to_string())));
+
+        // An empty side points at the line before the hunk
+        let hunks = diff_lines("", "added\n");
+        assert_eq!((hunks[0].old_start, hunks[0].old_len, hunks[0].new_start, hunks[0].new_len), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn identical_reports_have_no_changes() {
+        let old = report("old", &numbered(1..=5));
+        let new = report("new", &numbered(1..=5));
+        let diff = ReportDiff::between(&old, &new);
+
+        assert!(!diff.output_changed());
+        assert!(!diff.status.changed());
+        let m = &diff.metrics;
+        assert!(!m.duration_ms.changed() && !m.tokens_used.changed() && !m.cost.changed());
+        assert!(!m.api_calls.changed() && !m.errors.changed() && !m.warnings.changed());
+
+        let rendered = diff.render();
+        assert!(rendered.contains("Duration (ms): 1000 -> 1000 (0, 0%)\n"));
+        assert!(rendered.ends_with("\nOutput is identical\n"));
+        assert!(!rendered.contains("@@"));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::report::{Report, ReportStatus};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Csv,
+    JUnit,
+    /// The reports as a JSON array, in the shape they are stored in.
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Csv => "csv",
+            ExportFormat::JUnit => "xml",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+pub fn export_reports(reports: &[&Report], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Markdown => to_markdown(reports),
+        ExportFormat::Html => to_html(reports),
+        ExportFormat::Csv => to_csv(reports),
+        ExportFormat::JUnit => to_junit(reports),
+        ExportFormat::Json => serde_json::to_string_pretty(reports).unwrap_or_default(),
+    }
+}
+
+fn started_at(report: &Report) -> String {
+    DateTime::<Utc>::from(report.started_at()).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn tokens(report: &Report) -> String {
+    report
+        .metrics
+        .tokens_used
+        .map(|t| t.to_string())
+        .unwrap_or_default()
+}
+
+fn summary(reports: &[&Report]) -> String {
+    let count = |status: ReportStatus| reports.iter().filter(|r| r.status == status).count();
+    format!(
+        "{} runs: {} succeeded, {} failed, {} timed out, {} cancelled, {} running",
+        reports.len(),
+        count(ReportStatus::Success),
+        count(ReportStatus::Failed),
+        count(ReportStatus::TimedOut),
+        count(ReportStatus::Cancelled),
+        count(ReportStatus::Running),
+    )
+}
+
+fn to_markdown(reports: &[&Report]) -> String {
+    // Table cells can't contain pipes or line breaks
+    fn cell(text: &str) -> String {
+        text.replace('|', "\\|").replace(['\r', '\n'], " ")
+    }
+
+    let mut out = String::from("# Synapse Agent Reports\n\n");
+    out.push_str(&summary(reports));
+    out.push_str("\n\n| Status | Agent | Title | Started | Duration (ms) | Tokens | API calls | Errors | Warnings |\n");
+    out.push_str("|---|---|---|---|---:|---:|---:|---:|---:|\n");
+    for report in reports {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |\n",
+            report.status.as_str(),
+            cell(&report.agent_name),
+            cell(&report.title),
+            started_at(report),
+            report.duration_ms,
+            tokens(report),
+            report.metrics.api_calls,
+            report.metrics.errors,
+            report.metrics.warnings,
+        ));
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn to_html(reports: &[&Report]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Synapse Agent Reports</title>\n<style>\n\
+         body { background: #0a0a0a; color: #ffffff; font-family: Monaco, monospace; margin: 2em; }\n\
+         table { border-collapse: collapse; width: 100%; }\n\
+         th, td { border: 1px solid #333333; padding: 4px 8px; text-align: left; }\n\
+         th { background: #1a1a1a; }\n\
+         pre { background: #1a1a1a; padding: 8px; overflow-x: auto; }\n\
+         .Success { color: #00ff00; }\n\
+         .Failed { color: #ff6666; }\n\
+         .TimedOut { color: #ff9966; }\n\
+         .Cancelled { color: #888888; }\n\
+         .Running { color: #ffff00; }\n\
+         </style>\n</head>\n<body>\n<h1>Synapse Agent Reports</h1>\n",
+    );
+    out.push_str(&format!("<p>{}</p>\n", escape_html(&summary(reports))));
+    out.push_str(
+        "<table>\n<tr><th>Status</th><th>Agent</th><th>Title</th><th>Started</th><th>Duration (ms)</th>\
+         <th>Tokens</th><th>API calls</th><th>Errors</th><th>Warnings</th></tr>\n",
+    );
+    for report in reports {
+        out.push_str(&format!(
+            "<tr><td class=\"{status}\">{status}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&report.agent_name),
+            escape_html(&report.title),
+            started_at(report),
+            report.duration_ms,
+            tokens(report),
+            report.metrics.api_calls,
+            report.metrics.errors,
+            report.metrics.warnings,
+            status = report.status.as_str(),
+        ));
+    }
+    out.push_str("</table>\n");
+
+    for report in reports {
+        out.push_str(&format!(
+            "<details>\n<summary>{} &mdash; {} ({})</summary>\n<pre>{}</pre>\n</details>\n",
+            escape_html(&report.agent_name),
+            escape_html(&report.title),
+            report.status.as_str(),
+            escape_html(&report.output),
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn to_csv(reports: &[&Report]) -> String {
+    let mut out = String::from(
+        "id,title,agent_name,status,started_at,duration_ms,tokens_used,input_tokens,output_tokens,cost,api_calls,errors,warnings\n",
+    );
+    let optional = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+    for report in reports {
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            csv_field(&report.id),
+            csv_field(&report.title),
+            csv_field(&report.agent_name),
+            report.status.as_str(),
+            started_at(report),
+            report.duration_ms,
+            tokens(report),
+            optional(report.metrics.input_tokens),
+            optional(report.metrics.output_tokens),
+            report.metrics.cost.map(|c| format!("{:.6}", c)).unwrap_or_default(),
+            report.metrics.api_calls,
+            report.metrics.errors,
+            report.metrics.warnings,
+        ));
+    }
+    out
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters (e.g. ANSI escapes) are not allowed in XML 1.0
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// One `<testsuite>` per agent and one `<testcase>` per run. Failed and
+/// timed-out runs are failures, cancelled and still-running ones are
+/// reported as skipped.
+fn to_junit(reports: &[&Report]) -> String {
+    let mut suites: BTreeMap<&str, Vec<&Report>> = BTreeMap::new();
+    for report in reports {
+        suites.entry(report.agent_name.as_str()).or_default().push(report);
+    }
+
+    let failed = |report: &Report| matches!(report.status, ReportStatus::Failed | ReportStatus::TimedOut);
+    let failures = |runs: &[&Report]| runs.iter().filter(|r| failed(r)).count();
+    let skipped = |runs: &[&Report]| {
+        runs.iter()
+            .filter(|r| matches!(r.status, ReportStatus::Cancelled | ReportStatus::Running))
+            .count()
+    };
+    let seconds = |runs: &[&Report]| runs.iter().map(|r| r.duration_ms).sum::<u64>() as f64 / 1000.0;
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites name=\"synapse\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        reports.len(),
+        failures(reports),
+        skipped(reports),
+        seconds(reports),
+    ));
+
+    for (agent, runs) in &suites {
+        out.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            escape_xml(agent),
+            runs.len(),
+            failures(runs),
+            skipped(runs),
+            seconds(runs),
+        ));
+        for report in runs {
+            out.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\" timestamp=\"{}\">\n",
+                escape_xml(agent),
+                escape_xml(&report.title),
+                report.duration_ms as f64 / 1000.0,
+                started_at(report),
+            ));
+            match report.status {
+                ReportStatus::Failed => out.push_str(&format!(
+                    "      <failure message=\"Agent run failed\" type=\"AgentFailure\">{}</failure>\n",
+                    escape_xml(&report.output),
+                )),
+                ReportStatus::TimedOut => out.push_str(&format!(
+                    "      <failure message=\"Agent run timed out\" type=\"AgentTimeout\">{}</failure>\n",
+                    escape_xml(&report.output),
+                )),
+                ReportStatus::Cancelled => out.push_str("      <skipped message=\"Cancelled\"/>\n"),
+                ReportStatus::Running => out.push_str("      <skipped message=\"Still running\"/>\n"),
+                ReportStatus::Success => {}
+            }
+            if !failed(report) && !report.output.is_empty() {
+                out.push_str(&format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape_xml(&report.output)
+                ));
+            }
+            out.push_str("    </testcase>\n");
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(id: &str, title: &str, status: ReportStatus) -> Report {
+        let mut report = Report::new(id.to_string(), title.to_string(), "builder".to_string());
+        report.status = status;
+        report.started_at_ms = 1_738_195_200_000;
+        report.timestamp = 1_738_195_200;
+        report.duration_ms = 1500;
+        report.metrics.tokens_used = Some(1200);
+        report.metrics.api_calls = 3;
+        report
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_separators() {
+        let tricky = report("r1", "build, \"release\"\nstep 2", ReportStatus::Failed);
+        let plain = report("r2", "tests", ReportStatus::Success);
+        let csv = export_reports(&[&tricky, &plain], ExportFormat::Csv);
+
+        let (header, rows) = csv.split_once('\n').unwrap();
+        assert_eq!(header.split(',').count(), 13);
+        // The quoted title keeps its line break
+        assert_eq!(
+            rows,
+            "r1,\"build, \"\"release\"\"\nstep 2\",builder,Failed,2025-01-30T00:00:00.000Z,1500,1200,,,,3,0,0\n\
+             r2,tests,builder,Success,2025-01-30T00:00:00.000Z,1500,1200,,,,3,0,0\n"
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_table_cells() {
+        let report = report("r1", "a | b\nc", ReportStatus::TimedOut);
+        let markdown = export_reports(&[&report], ExportFormat::Markdown);
+
+        assert!(markdown.starts_with("# Synapse Agent Reports\n\n1 runs: 0 succeeded, 0 failed, 1 timed out, 0 cancelled, 0 running\n"));
+        assert!(markdown.ends_with(
+            "| TimedOut | builder | a \\| b c | 2025-01-30T00:00:00.000Z | 1500 | 1200 | 3 | 0 | 0 |\n"
+        ));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let mut failed = report("r1", "build", ReportStatus::Failed);
+        failed.output = "error: \"oops\"\n".to_string();
+        failed.metrics.cost = Some(0.25);
+        let json = export_reports(&[&failed], ExportFormat::Json);
+
+        let parsed: Vec<Report> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].id, "r1");
+        assert_eq!(parsed[0].status, ReportStatus::Failed);
+        assert_eq!(parsed[0].output, failed.output);
+        assert_eq!(parsed[0].metrics.cost, Some(0.25));
+        assert_eq!(export_reports(&[], ExportFormat::Json), "[]");
+    }
+}
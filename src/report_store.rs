@@ -0,0 +1,260 @@
+use crate::report::Report;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::cmp::Reverse;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Finished reports stored as JSON Lines, one file per UTC day of completion
+/// (`~/.synapse/reports/2025-01-31.jsonl`). Within a file reports are in
+/// completion order, so the newest report is the last line of the newest file.
+pub struct ReportStore {
+    dir: PathBuf,
+}
+
+impl ReportStore {
+    pub fn new() -> Self {
+        Self::with_dir(Self::get_reports_dir())
+    }
+
+    pub fn with_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn get_reports_dir() -> PathBuf {
+        if let Some(home) = dirs::home_dir() {
+            home.join(".synapse").join("reports")
+        } else {
+            PathBuf::from("./synapse_reports")
+        }
+    }
+
+    /// When the run finished, falling back to its start for reports that
+    /// predate completion times.
+    fn completed_at_ms(report: &Report) -> u64 {
+        report
+            .finished_at_ms
+            .unwrap_or_else(|| report.timestamp.saturating_mul(1000))
+    }
+
+    fn report_date(report: &Report) -> NaiveDate {
+        DateTime::<Utc>::from_timestamp_millis(Self::completed_at_ms(report) as i64)
+            .unwrap_or_default()
+            .date_naive()
+    }
+
+    pub fn append(&self, report: &Report) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self
+            .dir
+            .join(format!("{}.jsonl", Self::report_date(report).format("%Y-%m-%d")));
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(report)?)?;
+        Ok(())
+    }
+
+    /// Day files in the store, newest first.
+    fn day_files(&self) -> Vec<(NaiveDate, PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        let mut files: Vec<(NaiveDate, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "jsonl" {
+                    return None;
+                }
+                let date = NaiveDate::parse_from_str(path.file_stem()?.to_str()?, "%Y-%m-%d").ok()?;
+                Some((date, path))
+            })
+            .collect();
+        files.sort_by_key(|(date, _)| Reverse(*date));
+        files
+    }
+
+    /// Reads a day file, oldest report first. Malformed lines are skipped.
+    /// Files written before reports were filed by completion may be out of
+    /// order, so they are sorted on the way in.
+    fn read_file(path: &Path) -> Vec<Report> {
+        let Ok(file) = fs::File::open(path) else {
+            return Vec::new();
+        };
+        let mut reports: Vec<Report> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<Report>(&line).ok())
+            .collect();
+        reports.sort_by_key(Self::completed_at_ms);
+        reports
+    }
+
+    /// Returns up to `limit` reports, newest first, skipping the `offset`
+    /// newest ones. Only the day files covering that range are read.
+    pub fn load(&self, offset: usize, limit: usize) -> Vec<Report> {
+        let mut skipped = 0;
+        let mut page = Vec::new();
+
+        for (_, path) in self.day_files() {
+            if page.len() >= limit {
+                break;
+            }
+            for report in Self::read_file(&path).into_iter().rev() {
+                if skipped < offset {
+                    skipped += 1;
+                } else if page.len() < limit {
+                    page.push(report);
+                } else {
+                    break;
+                }
+            }
+        }
+        page
+    }
+
+    /// Deletes reports older than `retention_days` and all but the newest
+    /// `max_reports`. Zero disables the respective limit. Age is enforced per
+    /// day file. Returns the number of files removed or rewritten.
+    pub fn prune(&self, retention_days: u64, max_reports: usize) -> anyhow::Result<usize> {
+        let cutoff = (retention_days > 0)
+            .then(|| Utc::now().date_naive() - Duration::days(retention_days as i64));
+        let mut kept = 0;
+        let mut touched = 0;
+
+        for (date, path) in self.day_files() {
+            if cutoff.is_some_and(|cutoff| date < cutoff) || (max_reports > 0 && kept >= max_reports) {
+                fs::remove_file(&path)?;
+                touched += 1;
+                continue;
+            }
+
+            if max_reports == 0 {
+                continue;
+            }
+            let reports = Self::read_file(&path);
+            let room = max_reports - kept;
+            if reports.len() > room {
+                let mut contents = String::new();
+                for report in &reports[reports.len() - room..] {
+                    contents.push_str(&serde_json::to_string(report)?);
+                    contents.push('\n');
+                }
+                fs::write(&path, contents)?;
+                touched += 1;
+            }
+            kept += reports.len().min(room);
+        }
+        Ok(touched)
+    }
+}
+
+impl Default for ReportStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ReportStatus;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+    fn temp_store(name: &str) -> ReportStore {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        ReportStore::with_dir(std::env::temp_dir().join(format!(
+            "synapse-reports-{}-{}-{}",
+            name,
+            std::process::id(),
+            nanos
+        )))
+    }
+
+    fn finished(id: &str, finished_at_ms: u64) -> Report {
+        let mut report = Report::new(id.to_string(), format!("run {}", id), "agent".to_string());
+        report.status = ReportStatus::Success;
+        report.output = format!("output of {}\n", id);
+        report.started_at_ms = finished_at_ms - 1000;
+        report.timestamp = report.started_at_ms / 1000;
+        report.finished_at_ms = Some(finished_at_ms);
+        report
+    }
+
+    fn ids(reports: &[Report]) -> Vec<&str> {
+        reports.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    fn now_ms() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+    }
+
+    #[test]
+    fn reports_round_trip_newest_first() {
+        let store = temp_store("round-trip");
+        // 2025-01-30 and 2025-01-31, appended out of completion order
+        let day = 1_738_195_200_000;
+        store.append(&finished("b", day + DAY_MS + 5)).unwrap();
+        store.append(&finished("a", day + 5)).unwrap();
+        store.append(&finished("c", day + DAY_MS + 10)).unwrap();
+        let mut early = finished("d", day + DAY_MS + 7);
+        early.metrics.input_tokens = Some(42);
+        store.append(&early).unwrap();
+
+        let reports = store.load(0, 10);
+        assert_eq!(ids(&reports), vec!["c", "d", "b", "a"]);
+        assert_eq!(reports[1].metrics.input_tokens, Some(42));
+        assert_eq!(reports[1].output, "output of d\n");
+        assert!(store.dir.join("2025-01-31.jsonl").exists());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn pages_span_day_files() {
+        let store = temp_store("paging");
+        let day = 1_738_195_200_000;
+        for (i, id) in ["1", "2", "3", "4", "5"].iter().enumerate() {
+            // Two reports on the first day, three on the second
+            let offset = if i < 2 { 0 } else { DAY_MS };
+            store.append(&finished(id, day + offset + i as u64)).unwrap();
+        }
+
+        assert_eq!(ids(&store.load(0, 2)), vec!["5", "4"]);
+        assert_eq!(ids(&store.load(2, 2)), vec!["3", "2"]);
+        assert_eq!(ids(&store.load(4, 2)), vec!["1"]);
+        assert!(store.load(5, 2).is_empty());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_reports() {
+        let store = temp_store("prune-count");
+        let day = 1_738_195_200_000;
+        for (i, id) in ["1", "2", "3", "4", "5"].iter().enumerate() {
+            let offset = if i < 2 { 0 } else { DAY_MS };
+            store.append(&finished(id, day + offset + i as u64)).unwrap();
+        }
+
+        // The first day file goes, the second is rewritten
+        assert_eq!(store.prune(0, 2).unwrap(), 2);
+        assert_eq!(ids(&store.load(0, 10)), vec!["5", "4"]);
+        assert!(!store.dir.join("2025-01-30.jsonl").exists());
+        assert_eq!(store.prune(0, 2).unwrap(), 0);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn prune_drops_day_files_past_retention() {
+        let store = temp_store("prune-age");
+        let now = now_ms();
+        store.append(&finished("old", now - 40 * DAY_MS)).unwrap();
+        store.append(&finished("recent", now - 2 * DAY_MS)).unwrap();
+        store.append(&finished("today", now)).unwrap();
+
+        assert_eq!(store.prune(30, 0).unwrap(), 1);
+        assert_eq!(ids(&store.load(0, 10)), vec!["today", "recent"]);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
@@ -1,7 +1,8 @@
 // use gpui::*; // Commented out for CLI version
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
@@ -10,6 +11,12 @@ pub struct AppSettings {
     pub editor: EditorSettings,
     pub agents: AgentSettings,
     pub ui: UISettings,
+    #[serde(default)]
+    pub reports: ReportSettings,
+    #[serde(default)]
+    pub pricing: PricingSettings,
+    #[serde(default)]
+    pub logs: LogSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +34,104 @@ pub struct TerminalSettings {
     pub default_shell: String,
     pub scrollback_lines: usize,
     pub cursor_style: String,
+    #[serde(default)]
+    pub profiles: Vec<TerminalProfile>,
+    /// Name of the profile new terminals open with.
+    #[serde(default)]
+    pub default_profile: Option<String>,
+}
+
+impl TerminalSettings {
+    /// The named profile, with an empty command resolved to `default_shell`
+    /// and a missing scrollback length to `scrollback_lines`.
+    pub fn profile(&self, name: &str) -> Option<TerminalProfile> {
+        let mut profile = self.profiles.iter().find(|p| p.name == name)?.clone();
+        if profile.command.is_empty() {
+            profile.command = self.default_shell.clone();
+        }
+        profile.scrollback_lines.get_or_insert(self.scrollback_lines);
+        Some(profile)
+    }
+
+    /// The `default_profile`, or `default_shell` at 24x80 if there is none.
+    pub fn default_profile(&self) -> TerminalProfile {
+        self.default_profile
+            .as_deref()
+            .and_then(|name| self.profile(name))
+            .unwrap_or_else(|| TerminalProfile {
+                name: "Default".to_string(),
+                command: self.default_shell.clone(),
+                args: Vec::new(),
+                cwd: None,
+                env: HashMap::new(),
+                login: false,
+                rows: default_terminal_rows(),
+                cols: default_terminal_cols(),
+                scrollback_lines: Some(self.scrollback_lines),
+            })
+    }
+}
+
+/// How to launch a terminal: what to run, where, and at what size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalProfile {
+    pub name: String,
+    /// Program to run; empty for the default shell.
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Start the shell as a login shell (`-l`). Only applies when `command`
+    /// is one of the `LOGIN_SHELLS`; other programs are run as they are.
+    #[serde(default)]
+    pub login: bool,
+    #[serde(default = "default_terminal_rows")]
+    pub rows: u16,
+    #[serde(default = "default_terminal_cols")]
+    pub cols: u16,
+    /// Lines kept above the screen; `TerminalSettings::scrollback_lines` if
+    /// unset.
+    #[serde(default)]
+    pub scrollback_lines: Option<usize>,
+}
+
+/// Shells known to accept `-l` for a login shell.
+pub const LOGIN_SHELLS: &[&str] = &["sh", "bash", "zsh", "fish", "ksh", "mksh", "dash", "csh", "tcsh"];
+
+impl TerminalProfile {
+    /// Whether `command` is a shell that `login` applies to.
+    pub fn is_login_shell(&self) -> bool {
+        Path::new(&self.command)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| LOGIN_SHELLS.contains(&name))
+    }
+
+    /// Arguments to pass to `command`.
+    pub fn command_args(&self) -> Vec<String> {
+        let mut args = Vec::with_capacity(self.args.len() + 1);
+        if self.login && self.is_login_shell() {
+            args.push("-l".to_string());
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+pub fn default_terminal_rows() -> u16 {
+    24
+}
+
+pub fn default_terminal_cols() -> u16 {
+    80
+}
+
+pub fn default_scrollback_lines() -> usize {
+    1000
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,9 +148,158 @@ pub struct AgentSettings {
     pub auto_start: bool,
     pub max_concurrent: usize,
     pub timeout_seconds: u64,
+    /// Time an agent gets to exit after SIGINT/SIGTERM before it is killed.
+    #[serde(default = "default_stop_grace_seconds")]
+    pub stop_grace_seconds: u64,
     pub log_level: String,
 }
 
+pub fn default_stop_grace_seconds() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportSettings {
+    /// Write finished reports to `~/.synapse/reports/`.
+    pub persist: bool,
+    /// Days of history to keep; 0 keeps reports regardless of age.
+    pub retention_days: u64,
+    /// Maximum number of reports kept; 0 means unlimited.
+    pub max_reports: usize,
+    /// Number of stored reports loaded at a time.
+    pub page_size: usize,
+}
+
+impl Default for ReportSettings {
+    fn default() -> Self {
+        Self {
+            persist: true,
+            retention_days: 30,
+            max_reports: 1000,
+            page_size: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingSettings {
+    pub currency: String,
+    pub models: Vec<ModelPrice>,
+    /// Alert when a single run costs more than this.
+    pub run_budget: Option<f64>,
+    /// Alert when the runs started on one day cost more than this in total.
+    pub daily_budget: Option<f64>,
+}
+
+impl Default for PricingSettings {
+    fn default() -> Self {
+        Self {
+            currency: "USD".to_string(),
+            models: Vec::new(),
+            run_budget: None,
+            daily_budget: None,
+        }
+    }
+}
+
+/// Token prices for an AI CLI tool, optionally narrowed down to one model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub tool: String,
+    pub model: Option<String>,
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogSettings {
+    /// Append logs to `~/.synapse/logs/`.
+    pub persist: bool,
+    /// Rotate the current file once it grows past this size; 0 disables.
+    pub max_file_bytes: u64,
+    /// Rotate the current file once its first entry is this old; 0 disables.
+    pub max_file_age_hours: u64,
+    /// Gzip rotated files.
+    pub compress: bool,
+    /// Days rotated files are kept; 0 keeps them forever.
+    pub retention_days: u64,
+    /// `rfc3339`, or a chrono strftime pattern such as `%H:%M:%S%.3f`.
+    pub timestamp_format: String,
+    pub timezone: LogTimezone,
+    /// Plain text line layout. Placeholders: `{timestamp}`, `{level}`,
+    /// `{source}`, `{message}`, `{fields}` and `{field.<key>}`.
+    pub text_template: String,
+    /// APP-NAME of syslog lines.
+    pub app_name: String,
+    pub forward: LogForwardSettings,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[serde(rename = "jsonl")]
+    JsonLines,
+    Text,
+    /// RFC 5424
+    #[default]
+    Syslog,
+}
+
+/// Copies every log entry to a syslog socket or a file as it is logged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogForwardSettings {
+    pub enabled: bool,
+    pub sink: LogSink,
+    pub format: LogFormat,
+}
+
+impl Default for LogForwardSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sink: LogSink::Syslog {
+                socket: "/dev/log".to_string(),
+            },
+            format: LogFormat::Syslog,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LogSink {
+    /// Unix datagram socket of the local syslog daemon or journald.
+    Syslog { socket: String },
+    File { path: String },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTimezone {
+    #[default]
+    Local,
+    Utc,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            persist: true,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_file_age_hours: 24,
+            compress: true,
+            retention_days: 14,
+            timestamp_format: "%Y-%m-%d %H:%M:%S%.3f".to_string(),
+            timezone: LogTimezone::Local,
+            text_template: "{timestamp} [{level}] {source}: {message}".to_string(),
+            app_name: "synapse".to_string(),
+            forward: LogForwardSettings::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UISettings {
     pub show_status_bar: bool,
@@ -67,8 +321,10 @@ impl Default for AppSettings {
                 font_size: 12.0,
                 font_family: "Monaco".to_string(),
                 default_shell: "sh".to_string(),
-                scrollback_lines: 1000,
+                scrollback_lines: default_scrollback_lines(),
                 cursor_style: "block".to_string(),
+                profiles: Vec::new(),
+                default_profile: None,
             },
             editor: EditorSettings {
                 font_size: 14.0,
@@ -81,6 +337,7 @@ impl Default for AppSettings {
                 auto_start: false,
                 max_concurrent: 5,
                 timeout_seconds: 300,
+                stop_grace_seconds: default_stop_grace_seconds(),
                 log_level: "info".to_string(),
             },
             ui: UISettings {
@@ -89,6 +346,9 @@ impl Default for AppSettings {
                 roster_width: 0.15,
                 animation_enabled: true,
             },
+            reports: ReportSettings::default(),
+            pricing: PricingSettings::default(),
+            logs: LogSettings::default(),
         }
     }
 }
@@ -132,6 +392,11 @@ impl SettingsManager {
         &self.settings
     }
 
+    /// Picks up changes saved to the config file since it was loaded.
+    pub fn reload(&mut self) {
+        self.settings = Self::load_settings(&self.config_path);
+    }
+
     pub fn update<F>(&mut self, f: F) -> anyhow::Result<()>
     where
         F: FnOnce(&mut AppSettings),
@@ -161,3 +426,87 @@ impl Default for SettingsManager {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal_settings(profiles: &str) -> TerminalSettings {
+        let mut settings = AppSettings::default().terminal;
+        settings.default_shell = "/bin/zsh".to_string();
+        settings.scrollback_lines = 5000;
+        settings.profiles = serde_json::from_str(profiles).unwrap();
+        settings
+    }
+
+    #[test]
+    fn profile_fields_default_when_omitted() {
+        let settings = terminal_settings(r#"[{"name": "Shell"}]"#);
+        let profile = settings.profile("Shell").unwrap();
+
+        assert_eq!(profile.command, "/bin/zsh");
+        assert!(profile.args.is_empty());
+        assert_eq!(profile.cwd, None);
+        assert!(profile.env.is_empty());
+        assert!(!profile.login);
+        assert_eq!((profile.rows, profile.cols), (24, 80));
+        assert_eq!(profile.scrollback_lines, Some(5000));
+    }
+
+    #[test]
+    fn profile_parses_every_field() {
+        let settings = terminal_settings(
+            r#"[{
+                "name": "Aider",
+                "command": "aider",
+                "args": ["--model", "sonnet"],
+                "cwd": "/work/repo",
+                "env": {"NO_COLOR": "1"},
+                "rows": 40,
+                "cols": 120,
+                "scrollback_lines": 200
+            }]"#,
+        );
+        let profile = settings.profile("Aider").unwrap();
+
+        assert_eq!(profile.command, "aider");
+        assert_eq!(profile.command_args(), vec!["--model", "sonnet"]);
+        assert_eq!(profile.cwd, Some(PathBuf::from("/work/repo")));
+        assert_eq!(profile.env.get("NO_COLOR").map(String::as_str), Some("1"));
+        assert_eq!((profile.rows, profile.cols), (40, 120));
+        assert_eq!(profile.scrollback_lines, Some(200));
+        assert!(settings.profile("Missing").is_none());
+    }
+
+    #[test]
+    fn default_profile_falls_back_to_the_default_shell() {
+        let mut settings = terminal_settings(r#"[{"name": "Python", "command": "python3"}]"#);
+        assert_eq!(settings.default_profile().command, "/bin/zsh");
+        assert_eq!(settings.default_profile().scrollback_lines, Some(5000));
+
+        settings.default_profile = Some("Python".to_string());
+        assert_eq!(settings.default_profile().command, "python3");
+
+        settings.default_profile = Some("Missing".to_string());
+        assert_eq!(settings.default_profile().command, "/bin/zsh");
+    }
+
+    #[test]
+    fn login_flag_only_applies_to_shells() {
+        let settings = terminal_settings(
+            r#"[
+                {"name": "Login", "login": true, "args": ["-c", "true"]},
+                {"name": "Bash", "command": "/usr/local/bin/bash", "login": true},
+                {"name": "Python", "command": "python", "login": true},
+                {"name": "Top", "command": "/usr/bin/htop", "login": true},
+                {"name": "Plain", "command": "bash"}
+            ]"#,
+        );
+        let args = |name: &str| settings.profile(name).unwrap().command_args();
+
+        assert_eq!(args("Login"), vec!["-l", "-c", "true"]);
+        assert_eq!(args("Bash"), vec!["-l"]);
+        assert!(args("Python").is_empty());
+        assert!(args("Top").is_empty());
+        assert!(args("Plain").is_empty());
+    }
+}
@@ -1,49 +1,92 @@
+use crate::settings::{default_scrollback_lines, TerminalProfile};
+use crate::vt::{Cell, CellAttrs, Emulator};
 use gpui::*;
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
 use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+const DEFAULT_FG: u32 = 0x00ff00;
+const DEFAULT_BG: u32 = 0x000000;
+
+const FONT_FAMILY: &str = "Monaco";
+const FONT_SIZE: Pixels = px(12.0);
+const LINE_HEIGHT: Pixels = px(16.0);
+
 pub struct Terminal {
-    output: Arc<Mutex<String>>,
+    screen: Arc<Mutex<Emulator>>,
     pty: Option<Box<dyn portable_pty::MasterPty + Send>>,
-    writer: Option<Box<dyn Write + Send>>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    /// Lines scrolled back into the scrollback; 0 shows the live screen.
+    scroll_offset: usize,
 }
 
 impl Terminal {
-    pub fn new() -> Self {
-        let output = Arc::new(Mutex::new(String::new()));
-        let output_clone = output.clone();
+    /// Opens a PTY and starts the profile's command in it. Fails if the
+    /// command can't be started, e.g. because it or its `cwd` doesn't exist.
+    pub fn new(profile: &TerminalProfile) -> anyhow::Result<Self> {
+        let screen = Arc::new(Mutex::new(Emulator::new(
+            profile.rows as usize,
+            profile.cols as usize,
+            profile.scrollback_lines.unwrap_or_else(default_scrollback_lines),
+        )));
+        let screen_clone = screen.clone();
 
         let pty_system = native_pty_system();
         let pair = pty_system
             .openpty(PtySize {
-                rows: 24,
-                cols: 80,
+                rows: profile.rows,
+                cols: profile.cols,
                 pixel_width: 0,
                 pixel_height: 0,
             })
-            .expect("Failed to create PTY");
+            .map_err(|e| anyhow::anyhow!("Failed to create PTY: {}", e))?;
 
-        let cmd = CommandBuilder::new("sh");
+        let mut cmd = CommandBuilder::new(&profile.command);
+        cmd.args(profile.command_args());
+        if let Some(ref cwd) = profile.cwd {
+            cmd.cwd(cwd);
+        }
+        cmd.env("TERM", "xterm-256color");
+        for (key, value) in &profile.env {
+            cmd.env(key, value);
+        }
         let _child = pair
             .slave
             .spawn_command(cmd)
-            .expect("Failed to spawn command");
+            .map_err(|e| anyhow::anyhow!("Failed to start {}: {}", profile.command, e))?;
 
-        let mut reader = pair.master.try_clone_reader().expect("Failed to clone reader");
-        let writer = pair.master.take_writer().expect("Failed to take writer");
+        let mut reader = pair
+            .master
+            .try_clone_reader()
+            .map_err(|e| anyhow::anyhow!("Failed to read from PTY: {}", e))?;
+        let writer: Arc<Mutex<Box<dyn Write + Send>>> = Arc::new(Mutex::new(
+            pair.master
+                .take_writer()
+                .map_err(|e| anyhow::anyhow!("Failed to write to PTY: {}", e))?,
+        ));
+        let writer_clone = writer.clone();
 
         // Spawn thread to read from PTY
         thread::spawn(move || {
-            let mut buffer = [0u8; 1024];
+            let mut buffer = [0u8; 4096];
             loop {
                 match reader.read(&mut buffer) {
                     Ok(0) => break, // EOF
                     Ok(n) => {
-                        let text = String::from_utf8_lossy(&buffer[..n]);
-                        if let Ok(mut output) = output_clone.lock() {
-                            output.push_str(&text);
+                        let responses = match screen_clone.lock() {
+                            Ok(mut screen) => {
+                                screen.process(&buffer[..n]);
+                                screen.take_responses()
+                            }
+                            Err(_) => break,
+                        };
+                        // Answer cursor position and device attribute queries
+                        if !responses.is_empty() {
+                            if let Ok(mut writer) = writer_clone.lock() {
+                                let _ = writer.write_all(&responses);
+                                let _ = writer.flush();
+                            }
                         }
                     }
                     Err(_) => break,
@@ -51,15 +94,18 @@ impl Terminal {
             }
         });
 
-        Self {
-            output,
+        Ok(Self {
+            screen,
             pty: Some(Box::new(pair.master)),
-            writer: Some(writer),
-        }
+            writer,
+            scroll_offset: 0,
+        })
     }
 
     fn send_input(&mut self, input: &str, cx: &mut ViewContext<Self>) {
-        if let Some(ref mut writer) = self.writer {
+        // Typing jumps back to the live screen
+        self.scroll_offset = 0;
+        if let Ok(mut writer) = self.writer.lock() {
             if let Err(e) = writer.write_all(input.as_bytes()) {
                 // log::error!("Failed to write to PTY: {}", e);
             }
@@ -69,40 +115,155 @@ impl Terminal {
         }
         cx.notify();
     }
+
+    /// Resizes both the PTY and the emulated screen.
+    fn resize(&mut self, rows: u16, cols: u16) {
+        if let Some(ref pty) = self.pty {
+            let _ = pty.resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            });
+        }
+        if let Ok(mut screen) = self.screen.lock() {
+            screen.resize(rows as usize, cols as usize);
+        }
+    }
+
+    /// Fits the PTY and the screen to the cells that fit in `bounds`.
+    fn layout_changed(&mut self, bounds: Bounds<Pixels>, cx: &mut ViewContext<Self>) {
+        let cell = cell_size(cx);
+        let rows = (bounds.size.height / cell.height).floor().max(1.0) as u16;
+        let cols = (bounds.size.width / cell.width).floor().max(1.0) as u16;
+        let current = match self.screen.lock() {
+            Ok(screen) => (screen.rows() as u16, screen.cols() as u16),
+            Err(_) => return,
+        };
+        if current != (rows, cols) {
+            self.resize(rows, cols);
+            cx.notify();
+        }
+    }
+
+    fn scroll(&mut self, event: &ScrollWheelEvent, cx: &mut ViewContext<Self>) {
+        let lines = (event.delta.pixel_delta(LINE_HEIGHT).y / LINE_HEIGHT).round() as isize;
+        let max = match self.screen.lock() {
+            Ok(screen) => screen.max_scroll_offset(),
+            Err(_) => return,
+        };
+        let offset = self.scroll_offset.saturating_add_signed(lines).min(max);
+        if offset != self.scroll_offset {
+            self.scroll_offset = offset;
+            cx.notify();
+        }
+    }
+}
+
+/// Size of one cell in the terminal font.
+fn cell_size(cx: &WindowContext) -> Size<Pixels> {
+    let text_system = cx.text_system();
+    let font_id = text_system.resolve_font(&font(FONT_FAMILY));
+    let width = text_system
+        .advance(font_id, FONT_SIZE, 'm')
+        .map(|advance| advance.width)
+        .unwrap_or(FONT_SIZE * 0.6);
+    size(width, LINE_HEIGHT)
+}
+
+/// Splits a row into runs of cells that share attributes.
+fn runs(line: &[Cell], cursor_col: Option<usize>) -> Vec<(CellAttrs, String)> {
+    let mut runs: Vec<(CellAttrs, String)> = Vec::new();
+    for (col, cell) in line.iter().enumerate() {
+        // The wide character to the left already covers this column
+        if cell.width == 0 {
+            continue;
+        }
+        let mut attrs = cell.attrs;
+        if cursor_col == Some(col) {
+            attrs.inverse = !attrs.inverse;
+        }
+        let ch = if attrs.hidden { ' ' } else { cell.ch };
+        match runs.last_mut() {
+            Some((run_attrs, text)) if *run_attrs == attrs => text.push(ch),
+            _ => runs.push((attrs, ch.to_string())),
+        }
+    }
+    runs
+}
+
+fn run_element(attrs: CellAttrs, text: String) -> Div {
+    let mut fg = attrs.fg.to_rgb(DEFAULT_FG);
+    let mut bg = attrs.bg.to_rgb(DEFAULT_BG);
+    if attrs.inverse {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    let span = div().text_color(rgb(fg)).bg(rgb(bg)).child(text);
+    if attrs.bold {
+        span.font_weight(FontWeight::BOLD)
+    } else {
+        span
+    }
 }
 
 impl Render for Terminal {
-    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
-        let output = self.output.lock().unwrap().clone();
-        let lines: Vec<&str> = output.lines().collect();
-        let display_lines = if lines.len() > 100 {
-            lines[lines.len() - 100..].to_vec()
-        } else {
-            lines
-        };
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let screen = self.screen.lock().unwrap();
+        // Output may have cleared the scrollback or switched screens
+        self.scroll_offset = self.scroll_offset.min(screen.max_scroll_offset());
+        let cursor = screen.cursor();
+        let cursor_row = cursor.row + self.scroll_offset;
+        let rows: Vec<Vec<(CellAttrs, String)>> = screen
+            .viewport(self.scroll_offset)
+            .into_iter()
+            .enumerate()
+            .map(|(row, line)| {
+                let cursor_col = (screen.cursor_visible() && row == cursor_row).then_some(cursor.col);
+                runs(line, cursor_col)
+            })
+            .collect();
+        drop(screen);
+        let view = cx.view().downgrade();
 
         div()
             .flex()
             .flex_col()
             .w_full()
             .h_full()
-            .bg(rgb(0x000000))
-            .text_color(rgb(0x00ff00))
-            .font("Monaco")
-            .text_size(px(12.0))
+            .bg(rgb(DEFAULT_BG))
+            .text_color(rgb(DEFAULT_FG))
+            .font(FONT_FAMILY)
+            .text_size(FONT_SIZE)
+            .line_height(LINE_HEIGHT)
             .p_2()
-            .overflow_y_scroll()
+            .on_scroll_wheel(cx.listener(Self::scroll))
             .child(
                 div()
+                    .relative()
                     .flex()
                     .flex_col()
-                    .children(display_lines.iter().map(|line| {
+                    .size_full()
+                    .overflow_hidden()
+                    // Measures the cell area after layout to keep the PTY
+                    // sized to it
+                    .child(
+                        canvas(
+                            move |bounds, cx| {
+                                let _ = view.update(cx, |terminal, cx| terminal.layout_changed(bounds, cx));
+                            },
+                            |_, _, _| {},
+                        )
+                        .absolute()
+                        .size_full(),
+                    )
+                    .children(rows.into_iter().map(|runs| {
                         div()
+                            .flex()
+                            .flex_row()
                             .w_full()
-                            .child(line.to_string())
                             .whitespace_nowrap()
+                            .children(runs.into_iter().map(|(attrs, text)| run_element(attrs, text)))
                     })),
             )
     }
 }
-
@@ -0,0 +1,1294 @@
+use std::collections::VecDeque;
+use unicode_width::UnicodeWidthChar;
+use vte::{Params, Parser, Perform};
+
+/// Tab stops are every 8 columns until changed with HTS/TBC.
+const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Color {
+    #[default]
+    Default,
+    /// One of the 256 xterm palette colors.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Resolves the color to `0xRRGGBB`, using `default` for `Color::Default`.
+    pub fn to_rgb(self, default: u32) -> u32 {
+        match self {
+            Color::Default => default,
+            Color::Rgb(r, g, b) => (r as u32) << 16 | (g as u32) << 8 | b as u32,
+            Color::Indexed(index) => palette(index),
+        }
+    }
+}
+
+/// The xterm 256-color palette.
+fn palette(index: u8) -> u32 {
+    const ANSI: [u32; 16] = [
+        0x000000, 0xcd0000, 0x00cd00, 0xcdcd00, 0x0000ee, 0xcd00cd, 0x00cdcd, 0xe5e5e5,
+        0x7f7f7f, 0xff0000, 0x00ff00, 0xffff00, 0x5c5cff, 0xff00ff, 0x00ffff, 0xffffff,
+    ];
+    match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v as u32 * 40 };
+            let i = index - 16;
+            level(i / 36) << 16 | level(i / 6 % 6) << 8 | level(i % 6)
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) as u32 * 10;
+            gray << 16 | gray << 8 | gray
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CellAttrs {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub attrs: CellAttrs,
+    /// Columns the character takes: 2 for a wide (CJK, emoji) character,
+    /// 0 for the spacer cell to its right, 1 otherwise.
+    pub width: u8,
+}
+
+impl Cell {
+    fn blank(attrs: CellAttrs) -> Self {
+        Self { ch: ' ', attrs, width: 1 }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(CellAttrs::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Character sets that can be designated to G0 and G1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Charset {
+    #[default]
+    Ascii,
+    /// DEC Special Graphics, used for line drawing.
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    fn map(self, c: char) -> char {
+        match self {
+            Charset::Ascii => c,
+            Charset::DecSpecialGraphics => match c {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => c,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SavedCursor {
+    cursor: Cursor,
+    attrs: CellAttrs,
+    origin_mode: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+}
+
+/// Screen state driven by the escape sequences the parser dispatches.
+struct Screen {
+    rows: usize,
+    cols: usize,
+    lines: Vec<Vec<Cell>>,
+    /// The screen not currently shown: the primary screen while the
+    /// alternate one is active and vice versa.
+    inactive_lines: Vec<Vec<Cell>>,
+    alternate: bool,
+    scrollback: VecDeque<Vec<Cell>>,
+    scrollback_limit: usize,
+    cursor: Cursor,
+    cursor_visible: bool,
+    /// Set after printing in the last column; the next character wraps.
+    wrap_pending: bool,
+    saved_cursor: SavedCursor,
+    attrs: CellAttrs,
+    /// G0 and G1; SI selects G0 and SO selects G1.
+    charsets: [Charset; 2],
+    active_charset: usize,
+    /// Scrolling region, inclusive.
+    scroll_top: usize,
+    scroll_bottom: usize,
+    origin_mode: bool,
+    autowrap: bool,
+    insert_mode: bool,
+    application_cursor_keys: bool,
+    bracketed_paste: bool,
+    tab_stops: Vec<bool>,
+    title: String,
+    bell: bool,
+    last_printed: Option<char>,
+    /// Replies to queries (cursor position, device attributes) to be
+    /// written back to the program.
+    responses: Vec<u8>,
+}
+
+fn blank_line(cols: usize, attrs: CellAttrs) -> Vec<Cell> {
+    vec![Cell::blank(attrs); cols]
+}
+
+/// Blanks the remaining half of wide characters that were partly
+/// overwritten, erased or shifted off the line.
+fn repair_wide(line: &mut [Cell]) {
+    for col in 0..line.len() {
+        let broken = match line[col].width {
+            2 => line.get(col + 1).is_none_or(|next| next.width != 0),
+            0 => col == 0 || line[col - 1].width != 2,
+            _ => false,
+        };
+        if broken {
+            line[col] = Cell::blank(line[col].attrs);
+        }
+    }
+}
+
+fn default_tab_stops(cols: usize) -> Vec<bool> {
+    (0..cols).map(|col| col > 0 && col % TAB_WIDTH == 0).collect()
+}
+
+/// Removes rows until `lines` has `rows` left: lines above the cursor go
+/// first so the cursor keeps its content, then lines below it. Returns the
+/// lines removed from the top, oldest first.
+fn shrink_lines(lines: &mut Vec<Vec<Cell>>, rows: usize, cursor_row: &mut usize) -> Vec<Vec<Cell>> {
+    let mut removed = Vec::new();
+    while lines.len() > rows {
+        if *cursor_row > 0 {
+            removed.push(lines.remove(0));
+            *cursor_row -= 1;
+        } else {
+            lines.pop();
+        }
+    }
+    removed
+}
+
+impl Screen {
+    fn new(rows: usize, cols: usize, scrollback_limit: usize) -> Self {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        Self {
+            rows,
+            cols,
+            lines: vec![blank_line(cols, CellAttrs::default()); rows],
+            inactive_lines: vec![blank_line(cols, CellAttrs::default()); rows],
+            alternate: false,
+            scrollback: VecDeque::new(),
+            scrollback_limit,
+            cursor: Cursor::default(),
+            cursor_visible: true,
+            wrap_pending: false,
+            saved_cursor: SavedCursor::default(),
+            attrs: CellAttrs::default(),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            origin_mode: false,
+            autowrap: true,
+            insert_mode: false,
+            application_cursor_keys: false,
+            bracketed_paste: false,
+            tab_stops: default_tab_stops(cols),
+            title: String::new(),
+            bell: false,
+            last_printed: None,
+            responses: Vec::new(),
+        }
+    }
+
+    /// Blank cell carrying the current background, as erases use it.
+    fn erased(&self) -> Cell {
+        Cell::blank(CellAttrs {
+            bg: self.attrs.bg,
+            ..CellAttrs::default()
+        })
+    }
+
+    fn resize(&mut self, rows: usize, cols: usize) {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        for lines in [&mut self.lines, &mut self.inactive_lines] {
+            for line in lines.iter_mut() {
+                line.resize(cols, Cell::default());
+                repair_wide(line);
+            }
+        }
+
+        // Shrinking pushes lines above the cursor into scrollback first.
+        // While the alternate screen is shown, the primary screen's cursor
+        // is the one saved on entering it.
+        let scrolled_off = if self.alternate {
+            shrink_lines(&mut self.lines, rows, &mut self.cursor.row);
+            shrink_lines(&mut self.inactive_lines, rows, &mut self.saved_cursor.cursor.row)
+        } else {
+            self.inactive_lines.truncate(rows);
+            shrink_lines(&mut self.lines, rows, &mut self.cursor.row)
+        };
+        for line in scrolled_off {
+            self.push_scrollback(line);
+        }
+        self.lines.resize(rows, blank_line(cols, CellAttrs::default()));
+        self.inactive_lines.resize(rows, blank_line(cols, CellAttrs::default()));
+
+        self.rows = rows;
+        self.cols = cols;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        // Keep the stops that are still on screen; new columns get the
+        // default ones
+        let old_cols = self.tab_stops.len();
+        self.tab_stops.truncate(cols);
+        self.tab_stops.extend((old_cols..cols).map(|col| col % TAB_WIDTH == 0));
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn push_scrollback(&mut self, line: Vec<Cell>) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        self.scrollback.push_back(line);
+        while self.scrollback.len() > self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+    }
+
+    /// Scrolls the scrolling region up by `count` lines. Lines leaving the
+    /// top of a full-height region on the primary screen go to scrollback.
+    fn scroll_up(&mut self, count: usize) {
+        let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+        for _ in 0..count {
+            let line = self.lines.remove(self.scroll_top);
+            if self.scroll_top == 0 && !self.alternate {
+                self.push_scrollback(line);
+            }
+            let blank = vec![self.erased(); self.cols];
+            self.lines.insert(self.scroll_bottom, blank);
+        }
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        let count = count.min(self.scroll_bottom - self.scroll_top + 1);
+        for _ in 0..count {
+            self.lines.remove(self.scroll_bottom);
+            let blank = vec![self.erased(); self.cols];
+            self.lines.insert(self.scroll_top, blank);
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+    }
+
+    fn print_char(&mut self, c: char) {
+        let c = self.charsets[self.active_charset].map(c);
+        // Combining marks and other zero-width characters have no cell of
+        // their own and are dropped
+        let width = match c.width() {
+            Some(0) | None => return,
+            Some(width) => width.min(2).min(self.cols),
+        };
+
+        if self.wrap_pending && self.autowrap {
+            self.cursor.col = 0;
+            self.linefeed();
+        }
+        self.wrap_pending = false;
+
+        // A wide character that doesn't fit in the last column goes on the
+        // next line
+        if self.cursor.col + width > self.cols {
+            if self.autowrap {
+                self.erase_cells(self.cursor.row, self.cursor.col, self.cols);
+                self.cursor.col = 0;
+                self.linefeed();
+            } else {
+                self.cursor.col = self.cols - width;
+            }
+        }
+
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let attrs = self.attrs;
+        let line = &mut self.lines[row];
+        if self.insert_mode {
+            for _ in 0..width {
+                line.insert(col, Cell::default());
+            }
+            line.truncate(self.cols);
+        }
+        line[col] = Cell { ch: c, attrs, width: width as u8 };
+        if width == 2 {
+            line[col + 1] = Cell { ch: ' ', attrs, width: 0 };
+        }
+        repair_wide(line);
+
+        if col + width < self.cols {
+            self.cursor.col = col + width;
+        } else {
+            self.cursor.col = self.cols - 1;
+            self.wrap_pending = true;
+        }
+        self.last_printed = Some(c);
+    }
+
+    /// Rows the cursor may move within: the scrolling region in origin mode,
+    /// otherwise the whole screen.
+    fn row_bounds(&self) -> (usize, usize) {
+        if self.origin_mode {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        }
+    }
+
+    /// Moves to an absolute, 0-based position, relative to the scrolling
+    /// region in origin mode.
+    fn goto(&mut self, row: usize, col: usize) {
+        let (top, bottom) = self.row_bounds();
+        self.cursor.row = (top + row).min(bottom);
+        self.cursor.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn goto_row(&mut self, row: usize) {
+        self.goto(row, self.cursor.col);
+    }
+
+    fn goto_col(&mut self, col: usize) {
+        self.cursor.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Relative vertical movement stops at the scrolling region's edges when
+    /// starting inside it.
+    fn move_up(&mut self, count: usize) {
+        let top = if self.cursor.row >= self.scroll_top { self.scroll_top } else { 0 };
+        self.cursor.row = self.cursor.row.saturating_sub(count).max(top);
+        self.wrap_pending = false;
+    }
+
+    fn move_down(&mut self, count: usize) {
+        let bottom = if self.cursor.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.rows - 1
+        };
+        self.cursor.row = (self.cursor.row + count).min(bottom);
+        self.wrap_pending = false;
+    }
+
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+        let blank = self.erased();
+        let to = to.min(self.cols);
+        for cell in &mut self.lines[row][from.min(to)..to] {
+            *cell = blank;
+        }
+        repair_wide(&mut self.lines[row]);
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let Cursor { row, col } = self.cursor;
+        match mode {
+            0 => {
+                self.erase_cells(row, col, self.cols);
+                for r in row + 1..self.rows {
+                    self.erase_cells(r, 0, self.cols);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    self.erase_cells(r, 0, self.cols);
+                }
+                self.erase_cells(row, 0, col + 1);
+            }
+            2 => {
+                for r in 0..self.rows {
+                    self.erase_cells(r, 0, self.cols);
+                }
+            }
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+        self.wrap_pending = false;
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let Cursor { row, col } = self.cursor;
+        match mode {
+            0 => self.erase_cells(row, col, self.cols),
+            1 => self.erase_cells(row, 0, col + 1),
+            2 => self.erase_cells(row, 0, self.cols),
+            _ => {}
+        }
+        self.wrap_pending = false;
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        let count = count.min(self.scroll_bottom - self.cursor.row + 1);
+        for _ in 0..count {
+            self.lines.remove(self.scroll_bottom);
+            let blank = vec![self.erased(); self.cols];
+            self.lines.insert(self.cursor.row, blank);
+        }
+        self.cursor.col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        let count = count.min(self.scroll_bottom - self.cursor.row + 1);
+        for _ in 0..count {
+            self.lines.remove(self.cursor.row);
+            let blank = vec![self.erased(); self.cols];
+            self.lines.insert(self.scroll_bottom, blank);
+        }
+        self.cursor.col = 0;
+        self.wrap_pending = false;
+    }
+
+    fn insert_chars(&mut self, count: usize) {
+        let blank = self.erased();
+        let col = self.cursor.col;
+        let line = &mut self.lines[self.cursor.row];
+        let count = count.min(self.cols - col);
+        for _ in 0..count {
+            line.insert(col, blank);
+        }
+        line.truncate(self.cols);
+        repair_wide(line);
+        self.wrap_pending = false;
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let blank = self.erased();
+        let col = self.cursor.col;
+        let line = &mut self.lines[self.cursor.row];
+        let count = count.min(self.cols - col);
+        line.drain(col..col + count);
+        line.resize(self.cols, blank);
+        repair_wide(line);
+        self.wrap_pending = false;
+    }
+
+    fn tab(&mut self, count: usize) {
+        for _ in 0..count {
+            let next = (self.cursor.col + 1..self.cols).find(|&col| self.tab_stops[col]);
+            self.cursor.col = next.unwrap_or(self.cols - 1);
+        }
+        self.wrap_pending = false;
+    }
+
+    fn back_tab(&mut self, count: usize) {
+        for _ in 0..count {
+            let previous = (0..self.cursor.col).rev().find(|&col| self.tab_stops[col]);
+            self.cursor.col = previous.unwrap_or(0);
+        }
+        self.wrap_pending = false;
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = SavedCursor {
+            cursor: self.cursor,
+            attrs: self.attrs,
+            origin_mode: self.origin_mode,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor;
+        self.cursor = Cursor {
+            row: saved.cursor.row.min(self.rows - 1),
+            col: saved.cursor.col.min(self.cols - 1),
+        };
+        self.attrs = saved.attrs;
+        self.origin_mode = saved.origin_mode;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.wrap_pending = false;
+    }
+
+    fn set_alternate_screen(&mut self, enabled: bool, clear: bool) {
+        if enabled == self.alternate {
+            return;
+        }
+        std::mem::swap(&mut self.lines, &mut self.inactive_lines);
+        self.alternate = enabled;
+        if enabled && clear {
+            for line in &mut self.lines {
+                *line = blank_line(self.cols, CellAttrs::default());
+            }
+        }
+        self.wrap_pending = false;
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = if bottom == 0 { self.rows } else { bottom.min(self.rows) };
+        let top = top.max(1);
+        if top >= bottom {
+            return;
+        }
+        self.scroll_top = top - 1;
+        self.scroll_bottom = bottom - 1;
+        self.goto(0, 0);
+    }
+
+    fn reset(&mut self) {
+        let title = std::mem::take(&mut self.title);
+        let scrollback = std::mem::take(&mut self.scrollback);
+        let responses = std::mem::take(&mut self.responses);
+        *self = Screen::new(self.rows, self.cols, self.scrollback_limit);
+        self.title = title;
+        self.scrollback = scrollback;
+        self.responses = responses;
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            1 => self.application_cursor_keys = enabled,
+            6 => {
+                self.origin_mode = enabled;
+                self.goto(0, 0);
+            }
+            7 => self.autowrap = enabled,
+            25 => self.cursor_visible = enabled,
+            47 | 1047 => self.set_alternate_screen(enabled, mode == 1047),
+            1048 => {
+                if enabled {
+                    self.save_cursor()
+                } else {
+                    self.restore_cursor()
+                }
+            }
+            1049 => {
+                if enabled {
+                    self.save_cursor();
+                    self.set_alternate_screen(true, true);
+                } else {
+                    self.set_alternate_screen(false, false);
+                    self.restore_cursor();
+                }
+            }
+            2004 => self.bracketed_paste = enabled,
+            _ => {}
+        }
+    }
+
+    fn set_graphics_rendition(&mut self, params: &Params) {
+        let params: Vec<&[u16]> = params.iter().collect();
+        if params.is_empty() {
+            self.attrs = CellAttrs::default();
+            return;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            let param = params[i];
+            match param[0] {
+                0 => self.attrs = CellAttrs::default(),
+                1 => self.attrs.bold = true,
+                2 => self.attrs.dim = true,
+                3 => self.attrs.italic = true,
+                4 => self.attrs.underline = param.get(1) != Some(&0),
+                5 | 6 => self.attrs.blink = true,
+                7 => self.attrs.inverse = true,
+                8 => self.attrs.hidden = true,
+                9 => self.attrs.strikethrough = true,
+                21 => self.attrs.underline = true,
+                22 => {
+                    self.attrs.bold = false;
+                    self.attrs.dim = false;
+                }
+                23 => self.attrs.italic = false,
+                24 => self.attrs.underline = false,
+                25 => self.attrs.blink = false,
+                27 => self.attrs.inverse = false,
+                28 => self.attrs.hidden = false,
+                29 => self.attrs.strikethrough = false,
+                n @ 30..=37 => self.attrs.fg = Color::Indexed((n - 30) as u8),
+                39 => self.attrs.fg = Color::Default,
+                n @ 40..=47 => self.attrs.bg = Color::Indexed((n - 40) as u8),
+                49 => self.attrs.bg = Color::Default,
+                n @ 90..=97 => self.attrs.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => self.attrs.bg = Color::Indexed((n - 100 + 8) as u8),
+                38 | 48 => {
+                    let (color, consumed) = extended_color(&params[i..]);
+                    if let Some(color) = color {
+                        if param[0] == 38 {
+                            self.attrs.fg = color;
+                        } else {
+                            self.attrs.bg = color;
+                        }
+                    }
+                    i += consumed;
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn report(&mut self, reply: String) {
+        self.responses.extend_from_slice(reply.as_bytes());
+    }
+}
+
+/// Parses the color of an SGR 38/48 sequence, in either the colon form
+/// (`38:2::r:g:b`, `38:5:n`) or the semicolon form (`38;2;r;g;b`,
+/// `38;5;n`). Returns the color and the number of parameters used.
+fn extended_color(params: &[&[u16]]) -> (Option<Color>, usize) {
+    let first = params[0];
+    if first.len() > 1 {
+        let color = match first[1] {
+            5 => first.get(2).map(|&n| Color::Indexed(n as u8)),
+            // A color space id may precede the components
+            2 if first.len() >= 6 => Some(Color::Rgb(first[3] as u8, first[4] as u8, first[5] as u8)),
+            2 if first.len() == 5 => Some(Color::Rgb(first[2] as u8, first[3] as u8, first[4] as u8)),
+            _ => None,
+        };
+        return (color, 1);
+    }
+
+    let value = |i: usize| params.get(i).map(|p| p[0]);
+    match value(1) {
+        Some(5) => (value(2).map(|n| Color::Indexed(n as u8)), 3),
+        Some(2) => match (value(2), value(3), value(4)) {
+            (Some(r), Some(g), Some(b)) => (Some(Color::Rgb(r as u8, g as u8, b as u8)), 5),
+            _ => (None, params.len()),
+        },
+        _ => (None, 2.min(params.len())),
+    }
+}
+
+/// Parameter `index`, treating missing and zero values as `default`.
+fn param(params: &Params, index: usize, default: u16) -> u16 {
+    match params.iter().nth(index).map(|p| p[0]) {
+        Some(0) | None => default,
+        Some(value) => value,
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        self.print_char(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x07 => self.bell = true,
+            0x08 => {
+                self.cursor.col = self.cursor.col.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            0x09 => self.tab(1),
+            0x0a..=0x0c => self.linefeed(),
+            0x0d => {
+                self.cursor.col = 0;
+                self.wrap_pending = false;
+            }
+            // SO and SI shift to G1 and back to G0
+            0x0e => self.active_charset = 1,
+            0x0f => self.active_charset = 0,
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        // OSC 0 sets icon name and title, OSC 2 the title
+        if let [kind, title, ..] = params {
+            if *kind == b"0" || *kind == b"2" {
+                self.title = String::from_utf8_lossy(title).into_owned();
+            }
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        match (intermediates, action) {
+            ([], _) => {}
+            ([b'?'], 'h' | 'l') => {
+                for mode in params.iter() {
+                    self.set_private_mode(mode[0], action == 'h');
+                }
+                return;
+            }
+            // Secondary device attributes: a VT220-class terminal
+            ([b'>'], 'c') => {
+                self.report("\x1b[>1;10;0c".to_string());
+                return;
+            }
+            // Other `?`, `>`, `<` and `=` sequences (xterm modifyOtherKeys,
+            // kitty keyboard protocol, ...) and sequences with intermediates
+            // such as DECSCUSR are not supported. They must not fall through
+            // to the plain sequences sharing their final byte.
+            _ => return,
+        }
+        let n = |index: usize| param(params, index, 1) as usize;
+
+        match action {
+            'h' | 'l' => {
+                for mode in params.iter() {
+                    if mode[0] == 4 {
+                        self.insert_mode = action == 'h';
+                    }
+                }
+            }
+            '@' => self.insert_chars(n(0)),
+            'A' => self.move_up(n(0)),
+            'B' | 'e' => self.move_down(n(0)),
+            'C' | 'a' => self.goto_col(self.cursor.col + n(0)),
+            'D' => self.goto_col(self.cursor.col.saturating_sub(n(0))),
+            'E' => {
+                self.move_down(n(0));
+                self.cursor.col = 0;
+            }
+            'F' => {
+                self.move_up(n(0));
+                self.cursor.col = 0;
+            }
+            'G' | '`' => self.goto_col(n(0) - 1),
+            'H' | 'f' => self.goto(n(0) - 1, n(1) - 1),
+            'I' => self.tab(n(0)),
+            'J' => self.erase_in_display(param(params, 0, 0)),
+            'K' => self.erase_in_line(param(params, 0, 0)),
+            'L' => self.insert_lines(n(0)),
+            'M' => self.delete_lines(n(0)),
+            'P' => self.delete_chars(n(0)),
+            'S' => self.scroll_up(n(0)),
+            'T' => self.scroll_down(n(0)),
+            'X' => {
+                let col = self.cursor.col;
+                self.erase_cells(self.cursor.row, col, col + n(0));
+            }
+            'Z' => self.back_tab(n(0)),
+            'b' => {
+                if let Some(c) = self.last_printed {
+                    for _ in 0..n(0) {
+                        self.print_char(c);
+                    }
+                }
+            }
+            'c' => self.report("\x1b[?6c".to_string()),
+            'd' => self.goto_row(n(0) - 1),
+            'g' => match param(params, 0, 0) {
+                0 => self.tab_stops[self.cursor.col] = false,
+                3 => self.tab_stops.iter_mut().for_each(|stop| *stop = false),
+                _ => {}
+            },
+            'm' => self.set_graphics_rendition(params),
+            'n' => match param(params, 0, 0) {
+                5 => self.report("\x1b[0n".to_string()),
+                6 => {
+                    let (top, _) = self.row_bounds();
+                    let row = self.cursor.row.saturating_sub(top) + 1;
+                    self.report(format!("\x1b[{};{}R", row, self.cursor.col + 1));
+                }
+                _ => {}
+            },
+            'r' => self.set_scroll_region(param(params, 0, 1) as usize, param(params, 1, 0) as usize),
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore {
+            return;
+        }
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.linefeed();
+                self.cursor.col = 0;
+            }
+            ([], b'H') => self.tab_stops[self.cursor.col] = true,
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            // Designate G0 (`(`) or G1 (`)`); `0` is DEC Special Graphics,
+            // anything else is treated as ASCII
+            ([designator @ (b'(' | b')')], set) => {
+                let index = if *designator == b'(' { 0 } else { 1 };
+                self.charsets[index] = if set == b'0' {
+                    Charset::DecSpecialGraphics
+                } else {
+                    Charset::Ascii
+                };
+            }
+            // DECALN fills the screen with `E`
+            ([b'#'], b'8') => {
+                for line in &mut self.lines {
+                    for cell in line.iter_mut() {
+                        *cell = Cell {
+                            ch: 'E',
+                            ..Cell::default()
+                        };
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A VT100/xterm terminal emulator: feed it the bytes a program writes to
+/// its PTY and read back the resulting screen.
+pub struct Emulator {
+    parser: Parser,
+    screen: Screen,
+}
+
+impl Emulator {
+    /// `scrollback` is the number of lines kept above the primary screen.
+    pub fn new(rows: usize, cols: usize, scrollback: usize) -> Self {
+        Self {
+            parser: Parser::new(),
+            screen: Screen::new(rows, cols, scrollback),
+        }
+    }
+
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.advance(&mut self.screen, bytes);
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.screen.resize(rows, cols);
+    }
+
+    pub fn rows(&self) -> usize {
+        self.screen.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.screen.cols
+    }
+
+    /// The visible screen, top row first.
+    pub fn lines(&self) -> &[Vec<Cell>] {
+        &self.screen.lines
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.screen.lines.get(row)?.get(col)
+    }
+
+    /// A visible row as text, without trailing blanks.
+    pub fn line_text(&self, row: usize) -> String {
+        self.screen
+            .lines
+            .get(row)
+            .map(|line| {
+                line.iter()
+                    .filter(|cell| cell.width > 0)
+                    .map(|cell| cell.ch)
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Lines scrolled off the top of the primary screen, oldest first.
+    pub fn scrollback(&self) -> &VecDeque<Vec<Cell>> {
+        &self.screen.scrollback
+    }
+
+    /// How far back the screen can be scrolled. The alternate screen has no
+    /// scrollback.
+    pub fn max_scroll_offset(&self) -> usize {
+        if self.screen.alternate {
+            0
+        } else {
+            self.screen.scrollback.len()
+        }
+    }
+
+    /// The rows shown when scrolled `offset` lines back into the
+    /// scrollback, top row first.
+    pub fn viewport(&self, offset: usize) -> Vec<&[Cell]> {
+        let scrollback = &self.screen.scrollback;
+        let start = scrollback.len() - offset.min(self.max_scroll_offset());
+        scrollback
+            .range(start..)
+            .chain(self.screen.lines.iter())
+            .take(self.screen.rows)
+            .map(|line| line.as_slice())
+            .collect()
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.screen.cursor
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.screen.cursor_visible
+    }
+
+    /// Whether a full-screen program switched to the alternate screen.
+    pub fn alternate_screen(&self) -> bool {
+        self.screen.alternate
+    }
+
+    pub fn application_cursor_keys(&self) -> bool {
+        self.screen.application_cursor_keys
+    }
+
+    pub fn bracketed_paste(&self) -> bool {
+        self.screen.bracketed_paste
+    }
+
+    pub fn title(&self) -> &str {
+        &self.screen.title
+    }
+
+    /// Whether the bell rang since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.screen.bell)
+    }
+
+    /// Replies to terminal queries that must be written back to the PTY.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.screen.responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator(rows: usize, cols: usize, input: &str) -> Emulator {
+        let mut emulator = Emulator::new(rows, cols, 100);
+        emulator.process(input.as_bytes());
+        emulator
+    }
+
+    fn screen_text(emulator: &Emulator) -> Vec<String> {
+        (0..emulator.rows()).map(|row| emulator.line_text(row)).collect()
+    }
+
+    fn at(row: usize, col: usize) -> Cursor {
+        Cursor { row, col }
+    }
+
+    #[test]
+    fn sgr_sets_and_resets_attributes() {
+        let emulator = emulator(2, 10, "\x1b[1;4;31mA\x1b[0mB\x1b[7;93;44mC\x1b[27;39mD");
+
+        let a = emulator.cell(0, 0).unwrap().attrs;
+        assert!(a.bold && a.underline);
+        assert_eq!(a.fg, Color::Indexed(1));
+        assert_eq!(emulator.cell(0, 1).unwrap().attrs, CellAttrs::default());
+        let c = emulator.cell(0, 2).unwrap().attrs;
+        assert!(c.inverse);
+        assert_eq!((c.fg, c.bg), (Color::Indexed(11), Color::Indexed(4)));
+        let d = emulator.cell(0, 3).unwrap().attrs;
+        assert!(!d.inverse);
+        assert_eq!((d.fg, d.bg), (Color::Default, Color::Indexed(4)));
+    }
+
+    #[test]
+    fn sgr_parses_extended_colors() {
+        let emulator = emulator(1, 10, "\x1b[38;5;200;48;2;1;2;3mA\x1b[0;38:2::10:20:30;1mB");
+
+        let a = emulator.cell(0, 0).unwrap().attrs;
+        assert_eq!((a.fg, a.bg), (Color::Indexed(200), Color::Rgb(1, 2, 3)));
+        let b = emulator.cell(0, 1).unwrap().attrs;
+        assert_eq!((b.fg, b.bg), (Color::Rgb(10, 20, 30), Color::Default));
+        assert!(b.bold);
+    }
+
+    #[test]
+    fn cursor_movement_is_clamped_to_the_screen() {
+        let mut emulator = emulator(5, 10, "\x1b[3;5H");
+        assert_eq!(emulator.cursor(), at(2, 4));
+
+        emulator.process(b"\x1b[2A\x1b[3C");
+        assert_eq!(emulator.cursor(), at(0, 7));
+        emulator.process(b"\x1b[9A\x1b[99C");
+        assert_eq!(emulator.cursor(), at(0, 9));
+        emulator.process(b"\x1b[99B\x1b[99D");
+        assert_eq!(emulator.cursor(), at(4, 0));
+        emulator.process(b"\x1b[99;99H");
+        assert_eq!(emulator.cursor(), at(4, 9));
+        emulator.process(b"\x1b[H");
+        assert_eq!(emulator.cursor(), at(0, 0));
+    }
+
+    #[test]
+    fn text_wraps_at_the_last_column() {
+        let emulator = emulator(3, 4, "abcdef");
+        assert_eq!(screen_text(&emulator), vec!["abcd", "ef", ""]);
+        assert_eq!(emulator.cursor(), at(1, 2));
+    }
+
+    #[test]
+    fn scroll_region_scrolls_only_its_lines() {
+        let mut emulator = emulator(5, 10, "1\r\n2\r\n3\r\n4\r\n5");
+        emulator.process(b"\x1b[2;4r");
+        assert_eq!(emulator.cursor(), at(0, 0));
+
+        emulator.process(b"\x1b[4;1H\nx");
+        assert_eq!(screen_text(&emulator), vec!["1", "3", "4", "x", "5"]);
+        // Lines leaving a partial region don't go to scrollback
+        assert!(emulator.scrollback().is_empty());
+
+        emulator.process(b"\x1b[2;1H\x1bM");
+        assert_eq!(screen_text(&emulator), vec!["1", "", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn full_screen_scrolling_fills_scrollback() {
+        let emulator = emulator(2, 10, "1\r\n2\r\n3");
+        assert_eq!(screen_text(&emulator), vec!["2", "3"]);
+        assert_eq!(emulator.scrollback().len(), 1);
+        assert_eq!(emulator.scrollback()[0][0].ch, '1');
+    }
+
+    #[test]
+    fn invalid_scroll_regions_are_ignored() {
+        // Top below bottom, and a single-line region
+        let mut emulator = emulator(4, 10, "\x1b[2;2H\x1b[3;2r\x1b[2;2r");
+        assert_eq!(emulator.cursor(), at(1, 1));
+        emulator.process(b"1\r\n2\r\n3\r\n4\r\n5");
+        assert_eq!(screen_text(&emulator), vec!["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn scroll_region_bottom_is_clamped_and_reset() {
+        let mut emulator = emulator(4, 10, "\x1b[2;99r\x1b[4;1H1\n2");
+        // Region is rows 2-4, so row 1 stays put
+        emulator.process(b"\x1b[1;1Htop");
+        assert_eq!(screen_text(&emulator), vec!["top", "", "1", " 2"]);
+
+        // Without parameters the region is the whole screen again
+        emulator.process(b"\x1b[r\x1b[4;1H\n");
+        assert_eq!(screen_text(&emulator), vec!["", "1", " 2", ""]);
+    }
+
+    #[test]
+    fn origin_mode_positions_relative_to_the_region() {
+        let mut emulator = emulator(6, 10, "\x1b[3;5r\x1b[?6h");
+        assert_eq!(emulator.cursor(), at(2, 0));
+        emulator.process(b"\x1b[9;1H");
+        assert_eq!(emulator.cursor(), at(4, 0));
+
+        emulator.process(b"\x1b[6n");
+        assert_eq!(emulator.take_responses(), b"\x1b[3;1R");
+    }
+
+    #[test]
+    fn cursor_report_above_the_region_does_not_underflow() {
+        // Save in origin mode, then move the region below the saved row
+        let mut emulator = emulator(6, 10, "\x1b[3;5r\x1b[?6h\x1b7\x1b[4;5r\x1b8");
+        assert_eq!(emulator.cursor(), at(2, 0));
+
+        emulator.process(b"\x1b[6n");
+        assert_eq!(emulator.take_responses(), b"\x1b[1;1R");
+    }
+
+    #[test]
+    fn private_markers_do_not_run_plain_sequences() {
+        // vim's modifyOtherKeys request would otherwise set underline and bold
+        let mut emulator = emulator(3, 10, "\x1b[>4;2mx");
+        assert_eq!(emulator.cell(0, 0).unwrap().attrs, CellAttrs::default());
+
+        // Kitty keyboard protocol push, pop and query versus restore cursor
+        emulator.process(b"\x1b7\x1b[3;4H\x1b[>1u\x1b[<u\x1b[=5u\x1b[?u");
+        assert_eq!(emulator.cursor(), at(2, 3));
+
+        // Only the primary device attributes query gets the primary reply
+        emulator.process(b"\x1b[>c");
+        assert_eq!(emulator.take_responses(), b"\x1b[>1;10;0c");
+        emulator.process(b"\x1b[c");
+        assert_eq!(emulator.take_responses(), b"\x1b[?6c");
+
+        // Cursor style (DECSCUSR) doesn't touch the screen either
+        emulator.process(b"\x1b[2 q");
+        assert_eq!(emulator.cursor(), at(2, 3));
+    }
+
+    #[test]
+    fn private_modes_switch_screens_and_cursor() {
+        let mut emulator = emulator(2, 10, "main\x1b[?1049h\x1b[?25l");
+        assert!(emulator.alternate_screen());
+        assert!(!emulator.cursor_visible());
+        assert_eq!(screen_text(&emulator), vec!["", ""]);
+
+        emulator.process(b"alt\x1b[?1049l");
+        assert!(!emulator.alternate_screen());
+        assert_eq!(screen_text(&emulator), vec!["main", ""]);
+        assert_eq!(emulator.cursor(), at(0, 4));
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        let emulator = emulator(2, 10, "中a😀");
+
+        let wide = emulator.cell(0, 0).unwrap();
+        assert_eq!((wide.ch, wide.width), ('中', 2));
+        assert_eq!(emulator.cell(0, 1).unwrap().width, 0);
+        assert_eq!(emulator.cell(0, 2).unwrap().ch, 'a');
+        assert_eq!(emulator.cell(0, 3).unwrap().width, 2);
+        assert_eq!(emulator.cursor(), at(0, 5));
+        assert_eq!(emulator.line_text(0), "中a😀");
+    }
+
+    #[test]
+    fn wide_character_wraps_instead_of_splitting() {
+        let emulator = emulator(2, 5, "abcd中");
+        assert_eq!(screen_text(&emulator), vec!["abcd", "中"]);
+        assert_eq!(emulator.cursor(), at(1, 2));
+    }
+
+    #[test]
+    fn overwriting_half_a_wide_character_blanks_the_other_half() {
+        let mut emulator = emulator(1, 10, "中文");
+        emulator.process(b"\rx\x1b[4Gy");
+        assert_eq!(emulator.line_text(0), "x  y");
+        assert!(emulator.lines()[0].iter().all(|cell| cell.width == 1));
+    }
+
+    #[test]
+    fn combining_marks_are_dropped() {
+        let emulator = emulator(1, 10, "e\u{301}x");
+        assert_eq!(emulator.line_text(0), "ex");
+    }
+
+    #[test]
+    fn dec_special_graphics_draws_lines() {
+        let mut emulator = emulator(3, 10, "\x1b(0lqk\r\nx x\x1b(B\r\nmqj");
+        assert_eq!(screen_text(&emulator), vec!["┌─┐", "│ │", "mqj"]);
+
+        // G1 is only used while shifted out
+        emulator.process(b"\x1b[H\x1b)0q\x0eq\x0fq");
+        assert_eq!(emulator.line_text(0), "q─q");
+    }
+
+    #[test]
+    fn leaving_the_alternate_screen_after_shrinking_keeps_the_bottom_rows() {
+        let mut emulator = emulator(5, 10, "1\r\n2\r\n3\r\n4\r\n5");
+        emulator.process(b"\x1b[?1049h");
+        emulator.resize(3, 10);
+        emulator.process(b"\x1b[?1049l");
+
+        assert_eq!(screen_text(&emulator), vec!["3", "4", "5"]);
+        assert_eq!(emulator.cursor(), at(2, 1));
+        let scrollback: Vec<char> = emulator.scrollback().iter().map(|line| line[0].ch).collect();
+        assert_eq!(scrollback, vec!['1', '2']);
+    }
+
+    #[test]
+    fn resize_keeps_custom_tab_stops() {
+        let mut emulator = emulator(1, 10, "\x1b[3g\x1b[4G\x1bH\r");
+        emulator.resize(1, 20);
+
+        emulator.process(b"\t");
+        assert_eq!(emulator.cursor(), at(0, 3));
+        // Columns added by the resize get the default stops
+        emulator.process(b"\t");
+        assert_eq!(emulator.cursor(), at(0, 16));
+    }
+
+    #[test]
+    fn viewport_scrolls_back_into_the_scrollback() {
+        let mut emulator = emulator(2, 10, "1\r\n2\r\n3\r\n4");
+        let rows = |emulator: &Emulator, offset: usize| -> Vec<char> {
+            emulator.viewport(offset).iter().map(|line| line[0].ch).collect()
+        };
+        assert_eq!(emulator.max_scroll_offset(), 2);
+        assert_eq!(rows(&emulator, 0), vec!['3', '4']);
+        assert_eq!(rows(&emulator, 1), vec!['2', '3']);
+        assert_eq!(rows(&emulator, 99), vec!['1', '2']);
+
+        emulator.process(b"\x1b[?1049h");
+        assert_eq!(emulator.max_scroll_offset(), 0);
+        assert_eq!(rows(&emulator, 1), vec![' ', ' ']);
+    }
+}